@@ -0,0 +1,465 @@
+#![cfg(feature = "server")]
+//! Export of files as BagIt bags and RO-Crates, streamed to the browser as zip archives
+
+use std::io::{Read, Write};
+
+use serde_json::json;
+use sha2::Digest;
+
+use crate::download::{content_disposition, encode_uri};
+use crate::export::ArchiveFormat;
+use crate::serverfn::{FieldValue, File, FileMetadata};
+
+/// Query of an archive download
+#[derive(serde::Deserialize)]
+pub struct ArchiveQuery {
+    format: ArchiveFormat,
+    /// Directory of the files, relative to the root directory
+    #[serde(default)]
+    path: String,
+    /// Names of the files and directories to export, separated by slashes. All entries of the
+    /// directory are exported if empty.
+    #[serde(default)]
+    names: String,
+}
+
+/// A file or directory in an archive
+struct Entry {
+    /// Slash-separated path inside the archive payload
+    name: String,
+    path: std::path::PathBuf,
+    file: File,
+}
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        self.file.kind == "Directory"
+    }
+}
+
+/// Handler of `/archive`, responding with a zip archive written on a background thread while it is
+/// being downloaded
+pub async fn download(
+    axum::extract::Query(query): axum::extract::Query<ArchiveQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let (name, entries) = match entries(&query) {
+        Ok(entries) => entries,
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, format!("{e:#}")).into_response(),
+    };
+    let format = query.format;
+    let archive_name = name.clone();
+    let body = crate::download::stream(format!("{name} archive"), move |writer| match format {
+        ArchiveFormat::BagIt => write_bag(&archive_name, &entries, writer),
+        ArchiveFormat::RoCrate => write_ro_crate(&archive_name, &entries, writer),
+    });
+    let suffix = match format {
+        ArchiveFormat::BagIt => "bag",
+        ArchiveFormat::RoCrate => "ro-crate",
+    };
+    let disposition = content_disposition("attachment", &format!("{name}-{suffix}.zip"));
+    (
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                "application/zip".to_string(),
+            ),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// The name of the archive and the files and directories to put in it, directories before their
+/// contents
+fn entries(query: &ArchiveQuery) -> anyhow::Result<(String, Vec<Entry>)> {
+    let dir = crate::server::resolve_path(&query.path)?;
+    let names = if query.names.is_empty() {
+        std::fs::read_dir(&dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else {
+        query.names.split('/').map(str::to_string).collect()
+    };
+    let archive_name = match names.as_slice() {
+        [name] => name.clone(),
+        _ => query
+            .path
+            .rsplit('/')
+            .find(|c| !c.is_empty())
+            .unwrap_or("files")
+            .to_string(),
+    };
+    let mut metadata = crate::server::load_metadata()?;
+    let mut entries = Vec::new();
+    let mut queue = Vec::new();
+    for name in names {
+        crate::server::check_file_name(&name)?;
+        queue.push((name.clone(), dir.join(&name)));
+    }
+    while let Some((name, path)) = queue.pop() {
+        // Symbolic links are left out, they could lead out of the root directory
+        if std::fs::symlink_metadata(&path)?.is_symlink() {
+            continue;
+        }
+        let key = crate::server::join_path(&query.path, &name);
+        let mut file =
+            crate::server::file_info(&path).ok_or_else(|| anyhow::anyhow!("Can't read {key}"))?;
+        file.metadata = metadata.remove(&key).unwrap_or_default();
+        if file.kind == "Directory" {
+            for child in std::fs::read_dir(&path)? {
+                let child = child?;
+                let child_name = child.file_name().to_string_lossy().to_string();
+                queue.push((format!("{name}/{child_name}"), child.path()));
+            }
+        }
+        entries.push(Entry { name, path, file });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((archive_name, entries))
+}
+
+/// Options of an archive entry. Files are stored uncompressed, as data files are often compressed
+/// already and compressing them again would slow down the download.
+fn file_options(size: u64, modified: jiff::Timestamp) -> zip::write::SimpleFileOptions {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(size >= u64::from(u32::MAX));
+    let modified = modified.to_zoned(jiff::tz::TimeZone::UTC);
+    match zip::DateTime::from_date_and_time(
+        modified.year() as u16,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        modified.second() as u8,
+    ) {
+        Ok(modified) => options.last_modified_time(modified),
+        // Zip archives can't hold dates before 1980
+        Err(_) => options,
+    }
+}
+
+/// Copy the content of a file into an archive, returning its size and SHA-256
+fn copy_file(entry: &Entry, writer: &mut impl Write) -> anyhow::Result<(u64, String)> {
+    let mut file = std::fs::File::open(&entry.path)?;
+    let mut sha256 = sha2::Sha256::new();
+    let mut size = 0;
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        sha256.update(&buffer[..len]);
+        writer.write_all(&buffer[..len])?;
+        size += len as u64;
+    }
+    Ok((size, format!("{:x}", sha256.finalize())))
+}
+
+/// Write a bag with the files as payload, inside a directory named like the bag as is customary
+/// for serialized bags
+fn write_bag(bag: &str, entries: &[Entry], writer: impl Write) -> anyhow::Result<()> {
+    let mut zip = zip::ZipWriter::new_stream(writer);
+    let mut manifest = String::new();
+    let mut octets = 0;
+    let mut count = 0;
+    for entry in entries.iter().filter(|entry| !entry.is_dir()) {
+        let name = format!("data/{}", entry.name);
+        zip.start_file(
+            format!("{bag}/{name}"),
+            file_options(entry.file.size, entry.file.modified),
+        )?;
+        let (size, sha256) = copy_file(entry, &mut zip)?;
+        manifest += &format!("{sha256}  {}\n", encode_manifest_path(&name));
+        octets += size;
+        count += 1;
+    }
+    let tag_files = [
+        (
+            "bagit.txt",
+            "BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n".to_string(),
+        ),
+        (
+            "bag-info.txt",
+            format!(
+                "Bagging-Date: {}\nPayload-Oxum: {octets}.{count}\nBag-Software-Agent: Scout\n",
+                jiff::Zoned::now().date()
+            ),
+        ),
+        ("manifest-sha256.txt", manifest),
+    ];
+    let options = file_options(0, jiff::Timestamp::now());
+    let mut tag_manifest = String::new();
+    for (name, content) in tag_files {
+        zip.start_file(format!("{bag}/{name}"), options)?;
+        zip.write_all(content.as_bytes())?;
+        tag_manifest += &format!("{:x}  {name}\n", sha2::Sha256::digest(&content));
+    }
+    zip.start_file(format!("{bag}/tagmanifest-sha256.txt"), options)?;
+    zip.write_all(tag_manifest.as_bytes())?;
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Write an RO-Crate with the files at the root of the archive, described along with their tags,
+/// notes and fields in ro-crate-metadata.json
+fn write_ro_crate(name: &str, entries: &[Entry], writer: impl Write) -> anyhow::Result<()> {
+    let mut zip = zip::ZipWriter::new_stream(writer);
+    for entry in entries {
+        if entry.is_dir() {
+            zip.add_directory(
+                &entry.name,
+                file_options(entry.file.size, entry.file.modified),
+            )?;
+        } else {
+            zip.start_file(
+                &entry.name,
+                file_options(entry.file.size, entry.file.modified),
+            )?;
+            copy_file(entry, &mut zip)?;
+        }
+    }
+    // Entries directly inside a directory, or at the root for `None`
+    let parts = |dir: Option<&str>| {
+        entries
+            .iter()
+            .filter(|entry| entry.name.rsplit_once('/').map(|(parent, _)| parent) == dir)
+            .map(|entry| json!({ "@id": entity_id(entry) }))
+            .collect::<Vec<_>>()
+    };
+    let mut graph = vec![
+        json!({
+            "@id": "ro-crate-metadata.json",
+            "@type": "CreativeWork",
+            "conformsTo": { "@id": "https://w3id.org/ro/crate/1.1" },
+            "about": { "@id": "./" },
+        }),
+        json!({
+            "@id": "./",
+            "@type": "Dataset",
+            "name": name,
+            "datePublished": jiff::Zoned::now().date().to_string(),
+            "hasPart": parts(None),
+        }),
+    ];
+    for entry in entries {
+        let mut entity = json!({
+            "@id": entity_id(entry),
+            "name": entry.file.name,
+            "dateModified": entry.file.modified.to_string(),
+        });
+        if entry.is_dir() {
+            entity["@type"] = json!("Dataset");
+            entity["hasPart"] = json!(parts(Some(&entry.name)));
+        } else {
+            entity["@type"] = json!("File");
+            entity["contentSize"] = json!(entry.file.size.to_string());
+            entity["encodingFormat"] = json!(crate::server::mime_type(&entry.file.name));
+        }
+        describe(&mut entity, &entry.file.metadata, &entry.file.fields);
+        graph.push(entity);
+    }
+    let metadata = json!({
+        "@context": "https://w3id.org/ro/crate/1.1/context",
+        "@graph": graph,
+    });
+    zip.start_file(
+        "ro-crate-metadata.json",
+        file_options(0, jiff::Timestamp::now()),
+    )?;
+    serde_json::to_writer_pretty(&mut zip, &metadata)?;
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Add what Scout knows about a file to its RO-Crate entity: tags as keywords, notes as
+/// description and custom and extracted fields as additional properties
+fn describe(
+    entity: &mut serde_json::Value,
+    metadata: &FileMetadata,
+    fields: &[(String, FieldValue)],
+) {
+    if !metadata.tags.is_empty() {
+        entity["keywords"] = json!(metadata.tags);
+    }
+    if !metadata.notes.is_empty() {
+        entity["description"] = json!(metadata.notes);
+    }
+    let properties = metadata
+        .custom_fields
+        .iter()
+        .chain(fields.iter().map(|(name, value)| (name, value)))
+        .map(|(name, value)| {
+            let value = match value {
+                FieldValue::Text(text) => json!(text),
+                FieldValue::Number(number) => json!(number),
+                FieldValue::Date(date) => json!(date.to_string()),
+            };
+            json!({ "@type": "PropertyValue", "name": name, "value": value })
+        })
+        .collect::<Vec<_>>();
+    if !properties.is_empty() {
+        entity["additionalProperty"] = json!(properties);
+    }
+}
+
+/// Relative URI of a file or directory in an RO-Crate. Directories end with a slash.
+fn entity_id(entry: &Entry) -> String {
+    let id = itertools::Itertools::join(&mut entry.name.split('/').map(encode_uri), "/");
+    if entry.is_dir() { format!("{id}/") } else { id }
+}
+
+/// Encode a path for a BagIt manifest, where line breaks and percent signs must be encoded
+fn encode_manifest_path(path: &str) -> String {
+    path.replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    /// Files in a new directory with the given names and contents, as archive entries
+    fn entries(test: &str, files: &[(&str, &str)]) -> (std::path::PathBuf, Vec<Entry>) {
+        let dir = std::env::temp_dir().join(format!("scout-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut entries = Vec::new();
+        for (name, content) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, content).unwrap();
+            entries.push(Entry {
+                name: name.to_string(),
+                path,
+                file: File {
+                    name: name.rsplit('/').next().unwrap().to_string(),
+                    kind: "TXT".to_string(),
+                    size: content.len() as u64,
+                    modified: "2024-05-01T12:00:00Z".parse().unwrap(),
+                    metadata: Default::default(),
+                    fields: Vec::new(),
+                    fields_pending: false,
+                    checksum_pending: false,
+                },
+            });
+        }
+        (dir, entries)
+    }
+
+    /// The files of a zip archive by name
+    fn unzip(archive: Vec<u8>) -> BTreeMap<String, Vec<u8>> {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(archive)).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).unwrap();
+                let mut content = Vec::new();
+                file.read_to_end(&mut content).unwrap();
+                (file.name().to_string(), content)
+            })
+            .collect()
+    }
+
+    /// Digests and paths of a manifest
+    fn manifest(content: &[u8]) -> Vec<(String, String)> {
+        std::str::from_utf8(content)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let (digest, path) = line.split_once("  ").unwrap();
+                (digest.to_string(), path.to_string())
+            })
+            .collect()
+    }
+
+    fn sha256(content: &[u8]) -> String {
+        format!("{:x}", sha2::Sha256::digest(content))
+    }
+
+    #[test]
+    fn bag_manifests_match_zipped_files() {
+        let (dir, entries) = entries(
+            "bag",
+            &[
+                ("a.txt", "first"),
+                ("sub/b.txt", "second\n"),
+                ("100%.txt", ""),
+            ],
+        );
+        let mut archive = Vec::new();
+        write_bag("run", &entries, &mut archive).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        let files = unzip(archive);
+
+        let payload = manifest(&files["run/manifest-sha256.txt"]);
+        assert_eq!(payload.len(), 3);
+        for (digest, path) in &payload {
+            let name = path.replace("%25", "%");
+            assert_eq!(digest, &sha256(&files[&format!("run/{name}")]), "{path}");
+        }
+        assert!(payload.iter().any(|(_, path)| path == "data/100%25.txt"));
+
+        let tags = manifest(&files["run/tagmanifest-sha256.txt"]);
+        let tag_names = tags
+            .iter()
+            .map(|(_, path)| path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tag_names,
+            ["bagit.txt", "bag-info.txt", "manifest-sha256.txt"]
+        );
+        for (digest, path) in &tags {
+            assert_eq!(digest, &sha256(&files[&format!("run/{path}")]), "{path}");
+        }
+
+        let bag_info = String::from_utf8(files["run/bag-info.txt"].clone()).unwrap();
+        assert!(bag_info.contains("Payload-Oxum: 12.3\n"), "{bag_info}");
+        assert_eq!(
+            files["run/bagit.txt"],
+            b"BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n"
+        );
+    }
+
+    #[test]
+    fn ro_crate_describes_files() {
+        let (dir, mut entries) = entries("crate", &[("a b.txt", "first"), ("sub/c.txt", "third")]);
+        entries[0].file.metadata.tags.insert("raw".to_string());
+        entries.insert(
+            1,
+            Entry {
+                name: "sub".to_string(),
+                path: dir.join("sub"),
+                file: File {
+                    name: "sub".to_string(),
+                    kind: "Directory".to_string(),
+                    ..entries[1].file.clone()
+                },
+            },
+        );
+        let mut archive = Vec::new();
+        write_ro_crate("run", &entries, &mut archive).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        let files = unzip(archive);
+
+        assert_eq!(files["a b.txt"], b"first");
+        assert_eq!(files["sub/c.txt"], b"third");
+        let metadata: serde_json::Value =
+            serde_json::from_slice(&files["ro-crate-metadata.json"]).unwrap();
+        let graph = metadata["@graph"].as_array().unwrap();
+        let entity = |id: &str| graph.iter().find(|e| e["@id"] == id).unwrap();
+        assert_eq!(
+            entity("./")["hasPart"],
+            json!([{ "@id": "a%20b.txt" }, { "@id": "sub/" }])
+        );
+        assert_eq!(entity("sub/")["hasPart"], json!([{ "@id": "sub/c.txt" }]));
+        assert_eq!(entity("a%20b.txt")["keywords"], json!(["raw"]));
+        assert_eq!(entity("a%20b.txt")["contentSize"], "5");
+        assert_eq!(entity("sub/c.txt")["encodingFormat"], "text/plain");
+    }
+}
@@ -0,0 +1,262 @@
+#![cfg(feature = "server")]
+//! Checksums of file contents, computed one file at a time on a background worker
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use sha2::Digest;
+
+use crate::serverfn::Checksum;
+
+/// Files queued for or being hashed, with their path relative to the root directory
+static PENDING: LazyLock<Mutex<HashMap<PathBuf, String>>> = LazyLock::new(Default::default);
+
+/// Incremented whenever a file has been hashed, to wake up waiting requests
+static HASHED: LazyLock<tokio::sync::watch::Sender<u64>> =
+    LazyLock::new(|| tokio::sync::watch::Sender::new(0));
+
+/// How long checksums of files hashed one after another are collected before they are recorded
+/// together
+const BATCH_DURATION: Duration = Duration::from_secs(1);
+
+/// Queue of files to hash. A single worker keeps hashing from competing with itself for the disk.
+static QUEUE: LazyLock<Mutex<mpsc::Sender<PathBuf>>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    std::thread::spawn(move || {
+        while let Ok(path) = receiver.recv() {
+            let start = Instant::now();
+            let mut batch = vec![path];
+            let mut checksums = Vec::new();
+            loop {
+                checksums.extend(hash(batch.last().unwrap()));
+                if start.elapsed() >= BATCH_DURATION {
+                    break;
+                }
+                match receiver.try_recv() {
+                    Ok(path) => batch.push(path),
+                    Err(_) => break,
+                }
+            }
+            record(checksums);
+            let mut pending = PENDING.lock().unwrap();
+            for path in &batch {
+                pending.remove(path);
+            }
+            drop(pending);
+            HASHED.send_modify(|generation| *generation += 1);
+        }
+    });
+    Mutex::new(sender)
+});
+
+/// Queue a file for hashing unless it already is. `key` is its path relative to the root
+/// directory, under which the checksum is recorded.
+fn queue(path: &Path, key: &str) {
+    let mut pending = PENDING.lock().unwrap();
+    if !pending.contains_key(path) {
+        pending.insert(path.to_path_buf(), key.to_string());
+        drop(pending);
+        let _ = QUEUE.lock().unwrap().send(path.to_path_buf());
+    }
+}
+
+/// Queue a file for hashing if it has no checksum as of its current size and modification time.
+/// Returns whether the file is waiting to be hashed.
+pub fn queue_if_stale(path: &Path, key: &str, file: &crate::serverfn::File) -> bool {
+    if file.kind == "Directory" {
+        return false;
+    }
+    let current = file.metadata.checksum.as_ref().is_some_and(|checksum| {
+        checksum.size == file.size
+            && checksum.modified == file.modified
+            && (checksum.md5.is_some() || !crate::server::config().md5_checksums)
+    });
+    if !current {
+        queue(path, key);
+    }
+    PENDING.lock().unwrap().contains_key(path)
+}
+
+/// Queue a file for hashing again to check that its content still matches its checksum
+pub fn verify(path: &Path, key: &str) {
+    if path.is_file() {
+        queue(path, key);
+    }
+}
+
+/// The checksum of a queued file with the path it is recorded under
+fn hash(path: &Path) -> Option<(String, Checksum)> {
+    let key = PENDING.lock().unwrap().get(path).cloned()?;
+    match hash_file(path, crate::server::config().md5_checksums) {
+        Ok(checksum) => Some((key, checksum?)),
+        Err(e) => {
+            tracing::error!("Failed to hash {}: {e:#}", path.display());
+            None
+        }
+    }
+}
+
+/// Record new checksums, checking them against the previous ones of files that shouldn't have
+/// changed
+fn record(checksums: Vec<(String, Checksum)>) {
+    if checksums.is_empty() {
+        return;
+    }
+    let result = crate::server::update_metadata_batch(checksums, |metadata, new| {
+        metadata.checksum = Some(merge(metadata.checksum.take(), new));
+    });
+    if let Err(e) = result {
+        tracing::error!("Failed to record checksums: {e:#}");
+    }
+}
+
+/// The checksum to record for a file given its previous one. If the file hasn't changed as far as
+/// its size and modification time tell, the new checksum verifies the previous one.
+fn merge(old: Option<Checksum>, new: Checksum) -> Checksum {
+    match old {
+        Some(old) if old.size == new.size && old.modified == new.modified => {
+            if old.sha256 == new.sha256 {
+                Checksum {
+                    md5: new.md5.or(old.md5),
+                    verified: Some(jiff::Timestamp::now()),
+                    mismatch: None,
+                    ..old
+                }
+            } else {
+                Checksum {
+                    mismatch: Some(new.sha256),
+                    ..old
+                }
+            }
+        }
+        _ => new,
+    }
+}
+
+/// A new checksum of a file, or `None` if the file changed while being read. It will be queued
+/// again when listed.
+fn hash_file(path: &Path, md5: bool) -> anyhow::Result<Option<Checksum>> {
+    let stat = |file: &std::fs::File| -> anyhow::Result<(u64, jiff::Timestamp)> {
+        let metadata = file.metadata()?;
+        Ok((metadata.len(), metadata.modified()?.try_into()?))
+    };
+    let mut file = std::fs::File::open(path)?;
+    let before = stat(&file)?;
+    let mut sha256 = sha2::Sha256::new();
+    let mut md5 = md5.then(md5::Md5::new);
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        sha256.update(&buffer[..len]);
+        if let Some(md5) = &mut md5 {
+            md5.update(&buffer[..len]);
+        }
+    }
+    if stat(&file)? != before {
+        return Ok(None);
+    }
+    Ok(Some(Checksum {
+        sha256: format!("{:x}", sha256.finalize()),
+        md5: md5.map(|md5| format!("{:x}", md5.finalize())),
+        size: before.0,
+        modified: before.1,
+        verified: None,
+        mismatch: None,
+    }))
+}
+
+/// Wait until no file directly inside a directory is waiting to be hashed, or the timeout elapses
+pub async fn wait_for_directory(dir: &Path, timeout: std::time::Duration) {
+    wait_while(|path| path.parent() == Some(dir), timeout).await
+}
+
+/// Wait until none of the files is waiting to be hashed, or the timeout elapses
+pub async fn wait_for_files(paths: &[PathBuf], timeout: std::time::Duration) {
+    wait_while(|path| paths.iter().any(|p| p == path), timeout).await
+}
+
+/// Wait while a file matching the predicate is waiting to be hashed
+async fn wait_while(predicate: impl Fn(&Path) -> bool, timeout: std::time::Duration) {
+    let is_pending = || PENDING.lock().unwrap().keys().any(|path| predicate(path));
+    crate::server::wait_while(&HASHED, is_pending, timeout).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(sha256: &str, size: u64) -> Checksum {
+        Checksum {
+            sha256: sha256.to_string(),
+            md5: None,
+            size,
+            modified: "2024-05-01T12:00:00Z".parse().unwrap(),
+            verified: None,
+            mismatch: None,
+        }
+    }
+
+    #[test]
+    fn hashes_file_content() {
+        let path = std::env::temp_dir().join(format!("scout-{}-checksum.txt", std::process::id()));
+        std::fs::write(&path, "abc").unwrap();
+        let with_md5 = hash_file(&path, true).unwrap().unwrap();
+        let without_md5 = hash_file(&path, false).unwrap().unwrap();
+        let modified: jiff::Timestamp = std::fs::metadata(&path)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            with_md5.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            with_md5.md5.as_deref(),
+            Some("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert_eq!(with_md5.size, 3);
+        assert_eq!(with_md5.modified, modified);
+        assert_eq!(without_md5.sha256, with_md5.sha256);
+        assert_eq!(without_md5.md5, None);
+        assert!(hash_file(&path, false).is_err());
+    }
+
+    #[test]
+    fn same_content_verifies_previous_checksum() {
+        let old = Checksum {
+            md5: Some("md5".to_string()),
+            ..checksum("aa", 1)
+        };
+        let merged = merge(Some(old.clone()), checksum("aa", 1));
+        assert_eq!(merged.md5.as_deref(), Some("md5"));
+        assert!(merged.verified.is_some());
+        assert_eq!(merged.mismatch, None);
+        // A later mismatch is cleared once the content matches again
+        let changed = merge(Some(merged), checksum("bb", 1));
+        assert_eq!(changed.sha256, "aa");
+        assert_eq!(changed.mismatch.as_deref(), Some("bb"));
+        let restored = merge(Some(changed), checksum("aa", 1));
+        assert_eq!(restored.mismatch, None);
+    }
+
+    #[test]
+    fn changed_file_replaces_checksum() {
+        assert_eq!(merge(None, checksum("aa", 1)), checksum("aa", 1));
+        let resized = merge(Some(checksum("aa", 1)), checksum("bb", 2));
+        assert_eq!(resized, checksum("bb", 2));
+        let touched = Checksum {
+            modified: "2024-06-01T12:00:00Z".parse().unwrap(),
+            ..checksum("bb", 1)
+        };
+        assert_eq!(merge(Some(checksum("aa", 1)), touched.clone()), touched);
+    }
+}
@@ -0,0 +1,414 @@
+#![cfg(feature = "server")]
+//! Reading the header of DICOM files
+
+use std::io::Read;
+
+use crate::extract::MetadataExtractor;
+use crate::serverfn::{FieldInfo, FieldKind, FieldValue};
+
+/// Bytes read from the start of a file at most. The tags of interest come before the pixel data,
+/// so this is only reached with large private sequences.
+const MAX_HEADER_LEN: u64 = 1 << 20;
+
+const IMPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2";
+const EXPLICIT_VR_BIG_ENDIAN: &str = "1.2.840.10008.1.2.2";
+const DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1.99";
+
+const ITEM: Tag = (0xFFFE, 0xE000);
+const ITEM_DELIMITATION: Tag = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITATION: Tag = (0xFFFE, 0xE0DD);
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+/// Sequences nested deeper than this are taken as a corrupt header. Real files nest a few levels.
+const MAX_NESTING: usize = 32;
+
+const STUDY_DATE: Tag = (0x0008, 0x0020);
+const MODALITY: Tag = (0x0008, 0x0060);
+const SERIES_DESCRIPTION: Tag = (0x0008, 0x103E);
+const PATIENT_ID: Tag = (0x0010, 0x0020);
+const STUDY_INSTANCE_UID: Tag = (0x0020, 0x000D);
+const SERIES_INSTANCE_UID: Tag = (0x0020, 0x000E);
+
+type Tag = (u16, u16);
+
+/// Tags from the header of a DICOM file. Empty if missing.
+#[derive(Default)]
+struct DicomHeader {
+    patient_id: String,
+    study_instance_uid: String,
+    series_instance_uid: String,
+    modality: String,
+    study_date: Option<jiff::civil::Date>,
+    series_description: String,
+}
+
+/// Header tags of .dcm files and files without extension starting with the DICOM preamble. For
+/// directories, the tags of the first DICOM file directly inside, so directories holding a series
+/// can be grouped with their files.
+pub struct DicomExtractor;
+
+impl MetadataExtractor for DicomExtractor {
+    fn matches(&self, mime_type: &str, name: &str) -> bool {
+        matches!(mime_type, "application/dicom" | "inode/directory") || !name.contains('.')
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        vec![
+            FieldInfo::new("Patient ID", FieldKind::Category),
+            FieldInfo::new("Modality", FieldKind::Category),
+            FieldInfo::new("Study Date", FieldKind::Date),
+            FieldInfo::new("Study", FieldKind::Category),
+            FieldInfo::new("Series", FieldKind::Category),
+            FieldInfo::new("Series Description", FieldKind::Category),
+        ]
+    }
+
+    fn extract(&self, path: &std::path::Path) -> Option<Vec<(String, FieldValue)>> {
+        let header = if path.is_dir() {
+            read_directory_header(path)?
+        } else {
+            read_header(path)?
+        };
+        let text = |name: &str, value: String| (name.to_string(), FieldValue::Text(value));
+        let mut fields = vec![
+            text("Patient ID", header.patient_id),
+            text("Modality", header.modality),
+            text("Study", header.study_instance_uid),
+            text("Series", header.series_instance_uid),
+            text("Series Description", header.series_description),
+        ];
+        fields.extend(
+            header
+                .study_date
+                .map(|date| ("Study Date".to_string(), FieldValue::Date(date))),
+        );
+        fields.retain(|(_, value)| *value != FieldValue::Text(String::new()));
+        Some(fields)
+    }
+}
+
+/// Whether a file should be checked for a DICOM header: .dcm files and files without extension
+fn is_candidate(path: &std::path::Path) -> bool {
+    path.extension()
+        .is_none_or(|ext| ext.eq_ignore_ascii_case("dcm"))
+}
+
+/// The header of a DICOM file, or `None` if the file doesn't start with the DICOM preamble.
+/// Tags that are missing or can't be read are left empty.
+fn read_header(path: &std::path::Path) -> Option<DicomHeader> {
+    let mut data = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(MAX_HEADER_LEN)
+        .read_to_end(&mut data)
+        .ok()?;
+    parse_header(&data)
+}
+
+/// The header of the first DICOM file directly inside a directory. Only a few files are checked.
+fn read_directory_header(path: &std::path::Path) -> Option<DicomHeader> {
+    std::fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && is_candidate(path))
+        .take(8)
+        .find_map(|path| read_header(&path))
+}
+
+fn parse_header(data: &[u8]) -> Option<DicomHeader> {
+    if data.get(128..132)? != b"DICM" {
+        return None;
+    }
+    let mut header = DicomHeader::default();
+    // The file meta information is always explicit VR little endian
+    let mut reader = Reader {
+        data,
+        pos: 132,
+        explicit: true,
+        big_endian: false,
+        nesting: 0,
+    };
+    let mut transfer_syntax = String::new();
+    while reader.peek_tag().is_some_and(|(group, _)| group == 0x0002) {
+        let Some((tag, value)) = reader.element() else {
+            return Some(header);
+        };
+        if tag == (0x0002, 0x0010) {
+            transfer_syntax = text(value);
+        }
+    }
+    match transfer_syntax.as_str() {
+        IMPLICIT_VR_LITTLE_ENDIAN => reader.explicit = false,
+        EXPLICIT_VR_BIG_ENDIAN => reader.big_endian = true,
+        DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN => return Some(header),
+        _ => {}
+    }
+    // Elements are sorted by tag, so reading can stop after the last tag of interest
+    while let Some((tag, value)) = reader.element() {
+        match tag {
+            STUDY_DATE => {
+                header.study_date = jiff::civil::Date::strptime("%Y%m%d", text(value)).ok()
+            }
+            MODALITY => header.modality = text(value),
+            SERIES_DESCRIPTION => header.series_description = text(value),
+            PATIENT_ID => header.patient_id = text(value),
+            STUDY_INSTANCE_UID => header.study_instance_uid = text(value),
+            SERIES_INSTANCE_UID => header.series_instance_uid = text(value),
+            _ => {}
+        }
+        if tag >= SERIES_INSTANCE_UID {
+            break;
+        }
+    }
+    Some(header)
+}
+
+/// A string value without the padding DICOM adds to reach an even length
+fn text(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches(['\0', ' '])
+        .trim_start()
+        .to_string()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    explicit: bool,
+    big_endian: bool,
+    /// Number of sequences the reader is inside of
+    nesting: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn tag(&mut self) -> Option<Tag> {
+        Some((self.u16()?, self.u16()?))
+    }
+
+    fn peek_tag(&mut self) -> Option<Tag> {
+        let pos = self.pos;
+        let tag = self.tag();
+        self.pos = pos;
+        tag
+    }
+
+    /// The next data element with its value. Values of undefined length, i.e. sequences and
+    /// encapsulated pixel data, are skipped and returned empty. `None` at the end of the data or if
+    /// sequences are nested too deeply.
+    fn element(&mut self) -> Option<(Tag, &'a [u8])> {
+        let tag = self.tag()?;
+        let len = if self.explicit && tag.0 != 0xFFFE {
+            match self.bytes(2)? {
+                b"OB" | b"OD" | b"OF" | b"OL" | b"OV" | b"OW" | b"SQ" | b"SV" | b"UC" | b"UN"
+                | b"UR" | b"UT" | b"UV" => {
+                    self.bytes(2)?;
+                    self.u32()?
+                }
+                _ => u32::from(self.u16()?),
+            }
+        } else {
+            self.u32()?
+        };
+        if len == UNDEFINED_LENGTH {
+            if self.nesting == MAX_NESTING {
+                return None;
+            }
+            self.nesting += 1;
+            let skipped = self.skip_items();
+            self.nesting -= 1;
+            skipped?;
+            Some((tag, &[]))
+        } else {
+            Some((tag, self.bytes(len as usize)?))
+        }
+    }
+
+    /// Skip the items of a value of undefined length up to and including the sequence delimiter
+    fn skip_items(&mut self) -> Option<()> {
+        loop {
+            let tag = self.tag()?;
+            let len = self.u32()?;
+            match tag {
+                SEQUENCE_DELIMITATION => return Some(()),
+                ITEM if len == UNDEFINED_LENGTH => {
+                    while self.peek_tag()? != ITEM_DELIMITATION {
+                        self.element()?;
+                    }
+                    self.bytes(8)?;
+                }
+                ITEM => {
+                    self.bytes(len as usize)?;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pad a value to an even length as DICOM requires
+    fn padded(value: &[u8]) -> Vec<u8> {
+        let mut value = value.to_vec();
+        if value.len() % 2 == 1 {
+            value.push(b' ');
+        }
+        value
+    }
+
+    fn tag_bytes((group, element): Tag, big_endian: bool) -> Vec<u8> {
+        if big_endian {
+            [group.to_be_bytes(), element.to_be_bytes()].concat()
+        } else {
+            [group.to_le_bytes(), element.to_le_bytes()].concat()
+        }
+    }
+
+    fn explicit(tag: Tag, vr: &str, value: &[u8], big_endian: bool) -> Vec<u8> {
+        let value = padded(value);
+        let mut element = tag_bytes(tag, big_endian);
+        element.extend(vr.as_bytes());
+        let len = value.len() as u16;
+        element.extend(if big_endian {
+            len.to_be_bytes()
+        } else {
+            len.to_le_bytes()
+        });
+        element.extend(value);
+        element
+    }
+
+    fn implicit(tag: Tag, value: &[u8]) -> Vec<u8> {
+        let value = padded(value);
+        let mut element = tag_bytes(tag, false);
+        element.extend((value.len() as u32).to_le_bytes());
+        element.extend(value);
+        element
+    }
+
+    /// An element or item of undefined length in implicit VR little endian
+    fn undefined_length(tag: Tag) -> Vec<u8> {
+        [
+            tag_bytes(tag, false),
+            UNDEFINED_LENGTH.to_le_bytes().to_vec(),
+        ]
+        .concat()
+    }
+
+    fn delimiter(tag: Tag) -> Vec<u8> {
+        [tag_bytes(tag, false), 0u32.to_le_bytes().to_vec()].concat()
+    }
+
+    /// A file with the preamble, the file meta information and the given data set
+    fn file(transfer_syntax: &str, data_set: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 128];
+        data.extend(b"DICM");
+        let mut uid = transfer_syntax.as_bytes().to_vec();
+        if uid.len() % 2 == 1 {
+            uid.push(0);
+        }
+        data.extend(explicit((0x0002, 0x0010), "UI", &uid, false));
+        data.extend(data_set);
+        data
+    }
+
+    fn assert_header(header: DicomHeader) {
+        assert_eq!(header.study_date, Some(jiff::civil::date(2024, 3, 15)));
+        assert_eq!(header.modality, "MR");
+        assert_eq!(header.series_description, "T1 AX");
+        assert_eq!(header.patient_id, "P-007");
+        assert_eq!(header.study_instance_uid, "1.2.3.4");
+        assert_eq!(header.series_instance_uid, "1.2.3.4.5");
+    }
+
+    #[test]
+    fn implicit_vr_little_endian() {
+        let data_set = [
+            implicit(STUDY_DATE, b"20240315"),
+            implicit(MODALITY, b"MR"),
+            // A sequence of undefined length with an item of undefined length, to be skipped
+            undefined_length((0x0008, 0x1115)),
+            undefined_length(ITEM),
+            implicit((0x0008, 0x1150), b"1.2.9"),
+            delimiter(ITEM_DELIMITATION),
+            delimiter(SEQUENCE_DELIMITATION),
+            implicit(SERIES_DESCRIPTION, b"T1 AX"),
+            implicit(PATIENT_ID, b"P-007"),
+            implicit(STUDY_INSTANCE_UID, b"1.2.3.4\0"),
+            implicit(SERIES_INSTANCE_UID, b"1.2.3.4.5"),
+        ]
+        .concat();
+        assert_header(parse_header(&file(IMPLICIT_VR_LITTLE_ENDIAN, &data_set)).unwrap());
+    }
+
+    #[test]
+    fn explicit_vr_little_endian() {
+        let data_set = [
+            explicit(STUDY_DATE, "DA", b"20240315", false),
+            explicit(MODALITY, "CS", b"MR", false),
+            explicit(SERIES_DESCRIPTION, "LO", b"T1 AX", false),
+            explicit(PATIENT_ID, "LO", b"P-007", false),
+            explicit(STUDY_INSTANCE_UID, "UI", b"1.2.3.4\0", false),
+            explicit(SERIES_INSTANCE_UID, "UI", b"1.2.3.4.5", false),
+        ]
+        .concat();
+        assert_header(parse_header(&file("1.2.840.10008.1.2.1", &data_set)).unwrap());
+    }
+
+    #[test]
+    fn explicit_vr_big_endian() {
+        let data_set = [
+            explicit(STUDY_DATE, "DA", b"20240315", true),
+            explicit(MODALITY, "CS", b"MR", true),
+            explicit(SERIES_DESCRIPTION, "LO", b"T1 AX", true),
+            explicit(PATIENT_ID, "LO", b"P-007", true),
+            explicit(STUDY_INSTANCE_UID, "UI", b"1.2.3.4\0", true),
+            explicit(SERIES_INSTANCE_UID, "UI", b"1.2.3.4.5", true),
+        ]
+        .concat();
+        assert_header(parse_header(&file(EXPLICIT_VR_BIG_ENDIAN, &data_set)).unwrap());
+    }
+
+    #[test]
+    fn deeply_nested_sequences_stop_reading() {
+        let mut data_set = implicit(MODALITY, b"MR");
+        for _ in 0..100_000 {
+            data_set.extend(undefined_length((0x0008, 0x1115)));
+            data_set.extend(undefined_length(ITEM));
+        }
+        let header = parse_header(&file(IMPLICIT_VR_LITTLE_ENDIAN, &data_set)).unwrap();
+        assert_eq!(header.modality, "MR");
+        assert_eq!(header.patient_id, "");
+    }
+
+    #[test]
+    fn missing_preamble() {
+        assert!(parse_header(&[0; 256]).is_none());
+        assert!(parse_header(b"DICM").is_none());
+    }
+}
@@ -0,0 +1,326 @@
+#![cfg(feature = "server")]
+//! Files streamed to the browser by plain routes, as server functions can't be downloaded directly
+
+use std::io::Write;
+
+/// Query of a file download
+#[derive(serde::Deserialize)]
+pub struct FileQuery {
+    /// Directory of the file, relative to the root directory
+    #[serde(default)]
+    path: String,
+    name: String,
+    /// Show the file in the browser instead of saving it
+    #[serde(default)]
+    inline: bool,
+}
+
+/// Handler of `/file`, responding with the content of a file read on a background thread while it
+/// is being downloaded
+pub async fn file(
+    axum::extract::Query(query): axum::extract::Query<FileQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let (mut file, len) = match open(&query) {
+        Ok(file) => file,
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, format!("{e:#}")).into_response(),
+    };
+    let body = stream(query.name.clone(), move |writer| {
+        std::io::copy(&mut file, writer)?;
+        Ok(())
+    });
+    let disposition = if query.inline { "inline" } else { "attachment" };
+    (
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                crate::server::mime_type(&query.name).to_string(),
+            ),
+            (axum::http::header::CONTENT_LENGTH, len.to_string()),
+            (
+                axum::http::header::CONTENT_DISPOSITION,
+                content_disposition(disposition, &query.name),
+            ),
+            // Files are shown in the origin of the app, so keep HTML files from running scripts there
+            (
+                axum::http::header::CONTENT_SECURITY_POLICY,
+                "sandbox".to_string(),
+            ),
+            (
+                axum::http::header::X_CONTENT_TYPE_OPTIONS,
+                "nosniff".to_string(),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Query of a table export
+#[derive(serde::Deserialize)]
+pub struct TableQuery {
+    /// Directory listed by the table, relative to the root directory
+    #[serde(default)]
+    path: String,
+    format: crate::export::ExportFormat,
+    /// The view of the table as JSON, whose columns, filters and sort order the export follows
+    view: String,
+}
+
+/// Handler of `/export`, responding with the files of a directory as shown by a file table. Used
+/// for tables too large to export in the browser.
+pub async fn table(
+    axum::extract::Query(query): axum::extract::Query<TableQuery>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let (headers, rows) = match table_rows(&query).await {
+        Ok(table) => table,
+        Err(e) => return (axum::http::StatusCode::BAD_REQUEST, format!("{e:#}")).into_response(),
+    };
+    let format = query.format;
+    let dir_name = query.path.rsplit('/').find(|c| !c.is_empty());
+    let name = format!("{}.{}", dir_name.unwrap_or("files"), format.extension());
+    let body = stream(name.clone(), move |writer| {
+        crate::export::write_export(format, &headers, &rows, writer)
+    });
+    (
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                format.mime_type().to_string(),
+            ),
+            (
+                axum::http::header::CONTENT_DISPOSITION,
+                content_disposition("attachment", &name),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// The headers and rows of the requested table
+async fn table_rows(query: &TableQuery) -> anyhow::Result<(Vec<String>, Vec<Vec<String>>)> {
+    let view = serde_json::from_str(&query.view)?;
+    let files = crate::serverfn::get_files(query.path.clone())
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    let fields = crate::serverfn::get_metadata_fields()
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    let custom_fields = crate::serverfn::get_custom_fields()
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(file_table(&files, &fields, &custom_fields, &view))
+}
+
+/// The headers and rows of files as `FilePane` shows them in a view
+fn file_table(
+    files: &[crate::serverfn::File],
+    fields: &[crate::serverfn::FieldInfo],
+    custom_fields: &[crate::serverfn::FieldInfo],
+    view: &crate::table::View,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let name_column = crate::table::Column::new("Name");
+    let columns = crate::file_pane_columns(name_column, fields, custom_fields);
+    let data = files
+        .iter()
+        .map(|file| crate::file_pane_row(file, fields, custom_fields))
+        .collect::<Vec<_>>();
+    crate::table::view_rows(&columns, &data, view)
+}
+
+/// The requested file and its size
+fn open(query: &FileQuery) -> anyhow::Result<(std::fs::File, u64)> {
+    crate::server::check_file_name(&query.name)?;
+    let path = crate::server::resolve_path(&query.path)?.join(&query.name);
+    let metadata = std::fs::symlink_metadata(&path)?;
+    // Symbolic links could lead out of the root directory
+    if !metadata.is_file() {
+        anyhow::bail!("{} is not a file", query.name);
+    }
+    Ok((std::fs::File::open(path)?, metadata.len()))
+}
+
+/// Value of the Content-Disposition header for a file name, with a fallback for old browsers that
+/// don't understand encoded names
+pub fn content_disposition(disposition: &str, file_name: &str) -> String {
+    format!(
+        "{disposition}; filename=\"{}\"; filename*=UTF-8''{}",
+        file_name.replace(|c: char| !c.is_ascii_graphic() && c != ' ' || c == '"', "_"),
+        encode_uri(file_name),
+    )
+}
+
+/// Percent-encode all but the unreserved characters of RFC 3986
+pub fn encode_uri(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Response body written by a function on a background thread while it is being downloaded. If
+/// the function fails, the response is aborted so the download fails instead of ending with a
+/// truncated file. `name` identifies the download in the log.
+pub fn stream(
+    name: String,
+    write: impl FnOnce(&mut dyn Write) -> anyhow::Result<()> + Send + 'static,
+) -> axum::body::Body {
+    let (sender, receiver) = tokio::sync::mpsc::channel(8);
+    let errors = sender.clone();
+    std::thread::spawn(move || {
+        let mut writer = std::io::BufWriter::with_capacity(1 << 16, BodyWriter(sender));
+        let result = write(&mut writer).and_then(|()| Ok(writer.flush()?));
+        if let Err(e) = result {
+            tracing::error!("Failed to send {name}: {e:#}");
+            let _ = errors.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+    axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(receiver))
+}
+
+/// Sends what is written as chunks of a response body
+struct BodyWriter(tokio::sync::mpsc::Sender<std::io::Result<Vec<u8>>>);
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.blocking_send(Ok(buf.to_vec())).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Download was cancelled")
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(
+        path: &str,
+        name: &str,
+        inline: bool,
+    ) -> (axum::http::StatusCode, axum::http::HeaderMap, Vec<u8>) {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let response = file(axum::extract::Query(FileQuery {
+                path: path.to_string(),
+                name: name.to_string(),
+                inline,
+            }))
+            .await;
+            let (parts, body) = response.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            (parts.status, parts.headers, body.to_vec())
+        })
+    }
+
+    #[test]
+    fn streams_file() {
+        let (status, headers, body) = get("", "notes.txt", false);
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body, std::fs::read("testfiles/notes.txt").unwrap());
+        assert_eq!(headers["content-type"], "text/plain");
+        assert_eq!(headers["content-length"], body.len().to_string());
+        assert_eq!(
+            headers["content-disposition"],
+            "attachment; filename=\"notes.txt\"; filename*=UTF-8''notes.txt"
+        );
+
+        let (_, headers, _) = get("", "notes.txt", true);
+        assert!(
+            headers["content-disposition"]
+                .to_str()
+                .unwrap()
+                .starts_with("inline;")
+        );
+        assert_eq!(headers["content-security-policy"], "sandbox");
+    }
+
+    #[test]
+    fn rejects_paths_outside_files() {
+        for (path, name) in [
+            ("", ".."),
+            ("..", "Cargo.toml"),
+            ("", "missing.txt"),
+            ("", ""),
+        ] {
+            let (status, _, _) = get(path, name, false);
+            assert_eq!(status, axum::http::StatusCode::BAD_REQUEST, "{path}/{name}");
+        }
+    }
+
+    fn listed_file(name: &str, kind: &str, size: u64, tags: &[&str]) -> crate::serverfn::File {
+        crate::serverfn::File {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            size,
+            modified: "2024-05-01T12:00:00Z".parse().unwrap(),
+            metadata: crate::serverfn::FileMetadata {
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                ..Default::default()
+            },
+            fields: Vec::new(),
+            fields_pending: false,
+            checksum_pending: false,
+        }
+    }
+
+    #[test]
+    fn exports_files_as_shown_in_view() {
+        let files = [
+            listed_file("b.vcf", "VCF", 300, &["raw"]),
+            listed_file("a.vcf", "VCF", 2000, &["raw", "qc"]),
+            listed_file("notes.txt", "TXT", 10, &[]),
+        ];
+        let view = crate::table::View {
+            columns: vec!["Name".to_string(), "Size".to_string(), "Tags".to_string()],
+            sort_by: "Size".to_string(),
+            sort_ascending: false,
+            column_search_text: [("Type".to_string(), "vcf".to_string())].into(),
+            ..Default::default()
+        };
+        let (headers, rows) = file_table(&files, &[], &[], &view);
+        let csv = crate::export::export(crate::export::ExportFormat::Csv, &headers, &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "Name,Size,Tags\r\na.vcf,2000,\"qc, raw\"\r\nb.vcf,300,raw\r\n"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_table_queries() {
+        for (path, view) in [("", "not json"), ("..", "{}")] {
+            let response =
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(table(axum::extract::Query(TableQuery {
+                        path: path.to_string(),
+                        format: crate::export::ExportFormat::Csv,
+                        view: view.to_string(),
+                    })));
+            assert_eq!(
+                response.status(),
+                axum::http::StatusCode::BAD_REQUEST,
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_file_names() {
+        assert_eq!(
+            content_disposition("attachment", "Bericht \"Ä\".pdf"),
+            "attachment; filename=\"Bericht ___.pdf\"; filename*=UTF-8''Bericht%20%22%C3%84%22.pdf"
+        );
+    }
+}
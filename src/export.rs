@@ -0,0 +1,260 @@
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+/// Tables with more rows than this are exported on the server if the table supports it
+pub const SERVER_EXPORT_THRESHOLD: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    JsonLines,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [
+        ExportFormat::Csv,
+        ExportFormat::Tsv,
+        ExportFormat::JsonLines,
+        ExportFormat::Xlsx,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Tsv => "TSV",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Xlsx => "Excel (XLSX)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Tsv => "tsv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Tsv => "text/tab-separated-values",
+            ExportFormat::JsonLines => "application/jsonl",
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+        }
+    }
+}
+
+/// Packaging of files exported for sharing and archiving, delivered as a zip archive
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ArchiveFormat {
+    /// A bag as specified in RFC 8493, with the files as payload and SHA-256 manifests
+    #[serde(rename = "bagit")]
+    BagIt,
+    /// An RO-Crate 1.1 with the files described in ro-crate-metadata.json
+    #[serde(rename = "ro-crate")]
+    RoCrate,
+}
+
+/// Serialize the given rows in the given format
+pub fn export(
+    format: ExportFormat,
+    headers: &[String],
+    rows: &[Vec<String>],
+) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_export(format, headers, rows, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Write the given rows in the given format. Text formats are written row by row, so large exports
+/// can be streamed.
+pub fn write_export(
+    format: ExportFormat,
+    headers: &[String],
+    rows: &[Vec<String>],
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Csv => write_csv(headers, rows, writer),
+        ExportFormat::Tsv => write_tsv(headers, rows, writer),
+        ExportFormat::JsonLines => write_json_lines(headers, rows, writer),
+        ExportFormat::Xlsx => Ok(writer.write_all(&to_xlsx(headers, rows)?)?),
+    }
+}
+
+fn write_csv(
+    headers: &[String],
+    rows: &[Vec<String>],
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    // Quote fields as described in RFC 4180
+    fn escape(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+    for row in std::iter::once(headers).chain(rows.iter().map(Vec::as_slice)) {
+        let line = row.iter().map(|f| escape(f)).collect::<Vec<_>>().join(",");
+        write!(writer, "{line}\r\n")?;
+    }
+    Ok(())
+}
+
+fn write_tsv(
+    headers: &[String],
+    rows: &[Vec<String>],
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    // TSV has no quoting, so tabs and line breaks inside fields are replaced by spaces
+    fn escape(field: &str) -> String {
+        field.replace(['\t', '\n', '\r'], " ")
+    }
+    for row in std::iter::once(headers).chain(rows.iter().map(Vec::as_slice)) {
+        let line = row.iter().map(|f| escape(f)).collect::<Vec<_>>().join("\t");
+        writeln!(writer, "{line}")?;
+    }
+    Ok(())
+}
+
+fn write_json_lines(
+    headers: &[String],
+    rows: &[Vec<String>],
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    for row in rows {
+        let object = headers
+            .iter()
+            .cloned()
+            .zip(row.iter().cloned().map(serde_json::Value::String))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::to_writer(&mut *writer, &object)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+fn to_xlsx(headers: &[String], rows: &[Vec<String>]) -> anyhow::Result<Vec<u8>> {
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    let bold = rust_xlsxwriter::Format::new().set_bold();
+    for (col, header) in headers.iter().enumerate() {
+        worksheet.write_with_format(0, col as u16, header, &bold)?;
+    }
+    for (row_idx, row) in rows.iter().enumerate() {
+        for (col, cell) in row.iter().enumerate() {
+            worksheet.write(row_idx as u32 + 1, col as u16, cell)?;
+        }
+    }
+    worksheet.autofit();
+    Ok(workbook.save_to_buffer()?)
+}
+
+/// Offer the given bytes to the user as a file download
+pub fn download(file_name: &str, mime_type: &str, bytes: &[u8]) {
+    use base64::Engine;
+
+    let eval = dioxus::prelude::document::eval(
+        r#"
+        const [name, type, base64] = await dioxus.recv();
+        const bytes = Uint8Array.from(atob(base64), c => c.charCodeAt(0));
+        const url = URL.createObjectURL(new Blob([bytes], { type }));
+        const a = document.createElement("a");
+        a.href = url;
+        a.download = name;
+        a.click();
+        URL.revokeObjectURL(url);
+        "#,
+    );
+    // Base64 is much shorter than the JSON array of numbers bytes would be sent as
+    let base64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+    if let Err(e) = eval.send((file_name, mime_type, base64)) {
+        tracing::error!("Failed to start download: {e}");
+    }
+}
+
+/// What to do with the link to a file streamed by the server
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileLink {
+    /// Save the file
+    Download,
+    /// Show the file in a new tab, as far as the browser can display it
+    Open,
+    /// Copy the link for showing the file to the clipboard
+    Copy,
+}
+
+/// Download, open or copy the link of a file in a directory
+pub fn file_link(link: FileLink, path: &str, name: &str) {
+    let eval = dioxus::prelude::document::eval(
+        r#"
+        const [link, params] = await dioxus.recv();
+        if (link !== "download") params.inline = true;
+        const url = new URL("/file?" + new URLSearchParams(params), location.href).href;
+        if (link === "copy") {
+            await navigator.clipboard.writeText(url);
+        } else if (link === "open") {
+            window.open(url, "_blank", "noopener");
+        } else {
+            const a = document.createElement("a");
+            a.href = url;
+            a.click();
+        }
+        "#,
+    );
+    if let Err(e) = eval.send((link, serde_json::json!({ "path": path, "name": name }))) {
+        tracing::error!("Failed to link to {name}: {e}");
+    }
+}
+
+/// Download files and directories of a directory packaged as an archive. The archive is streamed
+/// from the server rather than built in the browser, so it can be larger than memory.
+pub fn download_archive(format: ArchiveFormat, path: &str, names: &[String]) {
+    let eval = dioxus::prelude::document::eval(
+        r#"
+        const params = await dioxus.recv();
+        const a = document.createElement("a");
+        a.href = "/archive?" + new URLSearchParams(params);
+        a.click();
+        "#,
+    );
+    // File names can't contain slashes, so they can be joined with them
+    let params = serde_json::json!({
+        "format": format,
+        "path": path,
+        "names": names.join("/"),
+    });
+    if let Err(e) = eval.send(params) {
+        tracing::error!("Failed to start download: {e}");
+    }
+}
+
+/// Download the files of a directory as a table in the columns, filters and sort order of a view.
+/// The table is streamed from the server, for tables too large to export in the browser.
+pub fn download_table(format: ExportFormat, path: &str, view: &crate::table::View) {
+    let eval = dioxus::prelude::document::eval(
+        r#"
+        const params = await dioxus.recv();
+        const a = document.createElement("a");
+        a.href = "/export?" + new URLSearchParams(params);
+        a.click();
+        "#,
+    );
+    let params = serde_json::json!({
+        "format": format,
+        "path": path,
+        "view": serde_json::to_string(view).unwrap_or_default(),
+    });
+    if let Err(e) = eval.send(params) {
+        tracing::error!("Failed to start download: {e}");
+    }
+}
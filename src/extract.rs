@@ -0,0 +1,222 @@
+#![cfg(feature = "server")]
+//! Extraction of metadata fields from file contents on background workers
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex, mpsc};
+use std::time::SystemTime;
+
+use crate::serverfn::{FieldInfo, FieldValue};
+
+/// Reads metadata fields from files of a format
+pub trait MetadataExtractor: Send + Sync {
+    /// Whether the extractor handles a file, given its MIME type and lowercase file name.
+    /// Directories have the MIME type "inode/directory".
+    fn matches(&self, mime_type: &str, name: &str) -> bool;
+
+    /// The fields the extractor can return. These are the columns offered by file tables.
+    fn fields(&self) -> Vec<FieldInfo>;
+
+    /// Read the fields of a file. Returns `None` if the file turns out not to be in the format.
+    /// Must not read more of a file than needed, as files can be many gigabytes.
+    fn extract(&self, path: &Path) -> Option<Vec<(String, FieldValue)>>;
+}
+
+/// The registered extractors. All matching extractors are applied to a file.
+static EXTRACTORS: LazyLock<Vec<Box<dyn MetadataExtractor>>> = LazyLock::new(|| {
+    vec![
+        Box::new(crate::dicom::DicomExtractor),
+        Box::new(crate::genomics::VcfExtractor),
+        Box::new(crate::genomics::FastqExtractor),
+        Box::new(crate::genomics::AlignmentExtractor),
+    ]
+});
+
+/// The fields of all registered extractors, without duplicates
+pub fn fields() -> Vec<FieldInfo> {
+    let mut fields = Vec::<FieldInfo>::new();
+    for field in EXTRACTORS.iter().flat_map(|e| e.fields()) {
+        if !fields.iter().any(|f| f.name == field.name) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+/// Extracted fields of a file as of its size and modification time
+struct CacheEntry {
+    size: u64,
+    modified: SystemTime,
+    fields: Vec<(String, FieldValue)>,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// Files queued for or being extracted
+    pending: HashSet<PathBuf>,
+}
+
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(Default::default);
+
+/// Incremented whenever a file has been extracted, to wake up waiting requests
+static EXTRACTED: LazyLock<tokio::sync::watch::Sender<u64>> =
+    LazyLock::new(|| tokio::sync::watch::Sender::new(0));
+
+/// Queue of files to extract, served by a few worker threads
+static QUEUE: LazyLock<Mutex<mpsc::Sender<PathBuf>>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    let receiver = std::sync::Arc::new(Mutex::new(receiver));
+    let workers = std::thread::available_parallelism().map_or(2, |n| n.get().min(4));
+    for _ in 0..workers {
+        let receiver = receiver.clone();
+        std::thread::spawn(move || {
+            loop {
+                let Ok(path) = receiver.lock().unwrap().recv() else {
+                    return;
+                };
+                extract(&path);
+            }
+        });
+    }
+    Mutex::new(sender)
+});
+
+fn extract(path: &Path) {
+    let metadata = std::fs::metadata(path).ok();
+    let fields = metadata.as_ref().map(|_| extract_fields(path));
+    let mut cache = CACHE.lock().unwrap();
+    cache.pending.remove(path);
+    if let (Some(metadata), Some(fields)) = (metadata, fields) {
+        cache.entries.insert(
+            path.to_path_buf(),
+            CacheEntry {
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                fields,
+            },
+        );
+    }
+    drop(cache);
+    EXTRACTED.send_modify(|generation| *generation += 1);
+}
+
+fn extract_fields(path: &Path) -> Vec<(String, FieldValue)> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mime_type = if path.is_dir() {
+        "inode/directory"
+    } else {
+        crate::server::mime_type(&name)
+    };
+    EXTRACTORS
+        .iter()
+        .filter(|extractor| extractor.matches(mime_type, &name))
+        .filter_map(|extractor| extractor.extract(path))
+        .flatten()
+        .collect()
+}
+
+/// The fields of a file if they have been extracted since it last changed. Otherwise the file is
+/// queued for extraction and `None` is returned.
+pub fn cached_fields(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+) -> Option<Vec<(String, FieldValue)>> {
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(entry) = cache.entries.get(path)
+        && entry.size == metadata.len()
+        && entry.modified == modified
+    {
+        return Some(entry.fields.clone());
+    }
+    if cache.pending.insert(path.to_path_buf()) {
+        drop(cache);
+        let _ = QUEUE.lock().unwrap().send(path.to_path_buf());
+    }
+    None
+}
+
+/// Wait until no file directly inside a directory is pending extraction, or the timeout elapses
+pub async fn wait_for_directory(dir: &Path, timeout: std::time::Duration) {
+    let is_pending = || {
+        CACHE
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .any(|path| path.parent() == Some(dir))
+    };
+    crate::server::wait_while(&EXTRACTED, is_pending, timeout).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_unique() {
+        let fields = fields();
+        assert!(itertools::Itertools::all_unique(
+            &mut fields.iter().map(|f| &f.name)
+        ));
+        assert!(fields.iter().any(|f| f.name == "VCF Version"));
+    }
+
+    #[test]
+    fn extracts_in_background_until_file_changes() {
+        let dir = std::env::temp_dir().join(format!("scout-{}-extract", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("calls.vcf");
+        let header = "##fileformat=VCFv4.2\n##reference=GRCh38\n##contig=<ID=chr1>\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\n";
+        std::fs::write(&path, header).unwrap();
+        let fields = |path: &Path| cached_fields(path, &std::fs::metadata(path).unwrap());
+        let wait = || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(wait_for_directory(&dir, std::time::Duration::from_secs(10)))
+        };
+
+        assert_eq!(fields(&path), None);
+        wait();
+        assert_eq!(
+            fields(&path),
+            Some(vec![
+                (
+                    "VCF Version".to_string(),
+                    FieldValue::Text("VCFv4.2".to_string())
+                ),
+                (
+                    "Reference".to_string(),
+                    FieldValue::Text("GRCh38".to_string())
+                ),
+                ("Reference Sequences".to_string(), FieldValue::Number(1.0)),
+                (
+                    "Samples".to_string(),
+                    FieldValue::Text("S1, S2".to_string())
+                ),
+            ])
+        );
+
+        // A changed size makes the cached fields stale
+        std::fs::write(&path, header.replace("S2", "S2\tS3")).unwrap();
+        assert_eq!(fields(&path), None);
+        wait();
+        assert!(fields(&path).unwrap().contains(&(
+            "Samples".to_string(),
+            FieldValue::Text("S1, S2, S3".to_string())
+        )));
+
+        // Files in no known format have no fields
+        std::fs::write(dir.join("notes.txt"), "notes").unwrap();
+        assert_eq!(fields(&dir.join("notes.txt")), None);
+        wait();
+        assert_eq!(fields(&dir.join("notes.txt")), Some(Vec::new()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
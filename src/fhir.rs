@@ -0,0 +1,495 @@
+#![cfg(feature = "server")]
+//! Client for the FHIR server configured in scout.toml
+
+use std::collections::HashSet;
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::serverfn::{DocumentReference, FhirPublication, Patient};
+
+/// Search results, one page at a time
+#[derive(Deserialize)]
+struct Bundle {
+    #[serde(default)]
+    entry: Vec<BundleEntry>,
+    #[serde(default)]
+    link: Vec<BundleLink>,
+}
+
+#[derive(Deserialize)]
+struct BundleEntry {
+    resource: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct BundleLink {
+    relation: String,
+    url: String,
+}
+
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Client {
+    pub fn new(config: &crate::server::Config) -> anyhow::Result<Self> {
+        let base_url = config
+            .fhir_base_url
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No FHIR server configured in scout.toml"))?;
+        let http = reqwest::Client::builder()
+            .danger_accept_invalid_certs(config.accept_invalid_certs)
+            .build()?;
+        Ok(Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            username: config.fhir_username.clone(),
+            password: config.fhir_password.clone(),
+        })
+    }
+
+    /// Client for the server in scout.toml
+    pub fn from_config() -> anyhow::Result<Self> {
+        Self::new(crate::server::config())
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .request(method, url)
+            .header(reqwest::header::ACCEPT, "application/fhir+json");
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    /// All resources of a type matching the search parameters, following the links to further pages.
+    /// Stops at a link back to a page already fetched, so a misbehaving server can't loop forever.
+    pub async fn search(
+        &self,
+        resource_type: &str,
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<Vec<serde_json::Value>> {
+        let mut resources = Vec::new();
+        let mut url =
+            reqwest::Url::parse_with_params(&format!("{}/{resource_type}", self.base_url), params)?;
+        let mut visited = HashSet::new();
+        loop {
+            visited.insert(url.clone());
+            let bundle: Bundle = self
+                .request(reqwest::Method::GET, url.as_str())
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            resources.extend(bundle.entry.into_iter().filter_map(|e| e.resource));
+            let Some(next) = bundle.link.into_iter().find(|l| l.relation == "next") else {
+                return Ok(resources);
+            };
+            url = url.join(&next.url)?;
+            if visited.contains(&url) {
+                tracing::warn!("FHIR search links back to the already fetched page {url}");
+                return Ok(resources);
+            }
+        }
+    }
+
+    pub async fn read(&self, resource_type: &str, id: &str) -> anyhow::Result<serde_json::Value> {
+        check_id(id)?;
+        let url = format!("{}/{resource_type}/{id}", self.base_url);
+        let response = self.request(reqwest::Method::GET, &url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            anyhow::bail!("{resource_type}/{id} not found");
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// Create a resource and return its id assigned by the server
+    pub async fn create(&self, resource: serde_json::Value) -> anyhow::Result<String> {
+        let resource_type = resource["resourceType"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Resource without resourceType"))?;
+        let url = format!("{}/{resource_type}", self.base_url);
+        let created: serde_json::Value = self
+            .request(reqwest::Method::POST, &url)
+            .header(reqwest::header::CONTENT_TYPE, "application/fhir+json")
+            .header("Prefer", "return=representation")
+            .json(&resource)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        created["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Server returned no id for the created {resource_type}"))
+    }
+
+    /// Upload the content of a file as a Binary and create a DocumentReference to it for a Patient
+    pub async fn publish(
+        &self,
+        name: &str,
+        content: Vec<u8>,
+        patient_id: &str,
+    ) -> anyhow::Result<FhirPublication> {
+        let content_type = crate::server::mime_type(name);
+        let size = content.len();
+        let binary_id = self
+            .create(json!({
+                "resourceType": "Binary",
+                "contentType": content_type,
+                "data": base64::engine::general_purpose::STANDARD.encode(content),
+            }))
+            .await?;
+        let published = jiff::Timestamp::now();
+        let document_reference_id = self
+            .create(json!({
+                "resourceType": "DocumentReference",
+                "status": "current",
+                "subject": { "reference": format!("Patient/{patient_id}") },
+                "date": published.to_string(),
+                "description": name,
+                "content": [{
+                    "attachment": {
+                        "contentType": content_type,
+                        "url": format!("Binary/{binary_id}"),
+                        "size": size,
+                        "title": name,
+                    }
+                }],
+            }))
+            .await?;
+        Ok(FhirPublication {
+            patient_id: patient_id.to_string(),
+            binary_id,
+            document_reference_id,
+            published,
+        })
+    }
+
+    /// DocumentReferences matching the search parameters, e.g. `subject`
+    pub async fn document_references(
+        &self,
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<Vec<DocumentReference>> {
+        Ok(self
+            .search("DocumentReference", params)
+            .await?
+            .iter()
+            .map(document_reference)
+            .collect())
+    }
+
+    pub async fn patient(&self, id: &str) -> anyhow::Result<Patient> {
+        Ok(patient(&self.read("Patient", id).await?))
+    }
+
+    /// Patients with an identifier equal to the query or a name containing it
+    pub async fn search_patients(&self, query: &str) -> anyhow::Result<Vec<Patient>> {
+        let mut patients = self
+            .search("Patient", &[("identifier", query)])
+            .await?
+            .iter()
+            .map(patient)
+            .collect::<Vec<_>>();
+        for found in self.search("Patient", &[("name", query)]).await? {
+            let found = patient(&found);
+            if !patients.iter().any(|p| p.id == found.id) {
+                patients.push(found);
+            }
+        }
+        Ok(patients)
+    }
+}
+
+/// Fail unless the id matches the FHIR id grammar, so it can't change the path or query of a URL.
+/// Ids of only dots match the grammar, but would be resolved as relative path segments.
+pub fn check_id(id: &str) -> anyhow::Result<()> {
+    let valid = (1..=64).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+        && id.bytes().any(|b| b != b'.');
+    if !valid {
+        anyhow::bail!("Invalid FHIR id {id:?}");
+    }
+    Ok(())
+}
+
+/// The fields of a DocumentReference shown in tables. Only the first attachment is considered.
+fn document_reference(resource: &serde_json::Value) -> DocumentReference {
+    let text = |value: &serde_json::Value| value.as_str().map(str::to_string);
+    let attachment = &resource["content"][0]["attachment"];
+    DocumentReference {
+        id: text(&resource["id"]).unwrap_or_default(),
+        title: text(&attachment["title"])
+            .or_else(|| text(&resource["description"]))
+            .unwrap_or_default(),
+        subject: text(&resource["subject"]["display"])
+            .or_else(|| text(&resource["subject"]["reference"]))
+            .unwrap_or_default(),
+        kind: text(&resource["type"]["text"])
+            .or_else(|| text(&resource["type"]["coding"][0]["display"]))
+            .or_else(|| text(&resource["type"]["coding"][0]["code"]))
+            .unwrap_or_default(),
+        date: resource["date"].as_str().and_then(|date| date.parse().ok()),
+        content_type: text(&attachment["contentType"]).unwrap_or_default(),
+        // An unsignedInt in R4, but an integer64 serialized as a string in R5
+        size: attachment["size"]
+            .as_u64()
+            .or_else(|| attachment["size"].as_str()?.parse().ok()),
+    }
+}
+
+fn patient(resource: &serde_json::Value) -> Patient {
+    let text = |value: &serde_json::Value| value.as_str().map(str::to_string);
+    let name = &resource["name"][0];
+    let name = text(&name["text"]).unwrap_or_else(|| {
+        let given = name["given"].as_array().into_iter().flatten();
+        given
+            .chain([&name["family"]])
+            .filter_map(|part| part.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    Patient {
+        id: text(&resource["id"]).unwrap_or_default(),
+        name,
+        identifiers: resource["identifier"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|identifier| identifier["value"].as_str())
+            .map(str::to_string)
+            .collect(),
+        birth_date: text(&resource["birthDate"]).unwrap_or_default(),
+        gender: text(&resource["gender"]).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::Json;
+    use axum::extract::{Path, Query, State};
+    use axum::routing::get;
+
+    use super::*;
+
+    /// Serve a FHIR server mock on a local port and run the test against a client for it.
+    /// DocumentReferences are split over two pages, Patient searches link back to their first page.
+    fn with_server<F: Future<Output = ()>>(test: impl FnOnce(Client, Arc<AtomicUsize>) -> F) {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base_url = format!("http://{}/fhir", listener.local_addr().unwrap());
+            let requests = Arc::new(AtomicUsize::new(0));
+            let router = axum::Router::new()
+                .route("/fhir/DocumentReference", get(document_references))
+                .route("/fhir/Patient", get(patients))
+                .route("/fhir/Patient/:id", get(read_patient))
+                .with_state((base_url.clone(), requests.clone()));
+            tokio::spawn(async { axum::serve(listener, router).await.unwrap() });
+            let client = Client::new(&crate::server::Config {
+                fhir_base_url: Some(base_url),
+                ..Default::default()
+            })
+            .unwrap();
+            test(client, requests).await;
+        });
+    }
+
+    type MockState = (String, Arc<AtomicUsize>);
+
+    fn bundle(resources: &[serde_json::Value], next: Option<String>) -> Json<serde_json::Value> {
+        Json(json!({
+            "resourceType": "Bundle",
+            "type": "searchset",
+            "entry": resources.iter().map(|r| json!({ "resource": r })).collect::<Vec<_>>(),
+            "link": next.map(|url| json!([{ "relation": "next", "url": url }])).unwrap_or(json!([])),
+        }))
+    }
+
+    async fn document_references(
+        State((base_url, requests)): State<MockState>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Json<serde_json::Value> {
+        requests.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(query["subject"], "Patient/p1");
+        match query.get("page").map(String::as_str) {
+            None => bundle(
+                // An R4 server, size is a number
+                &[json!({
+                    "resourceType": "DocumentReference",
+                    "id": "d1",
+                    "subject": { "reference": "Patient/p1" },
+                    "type": { "coding": [{ "code": "report" }] },
+                    "date": "2024-05-01T12:00:00Z",
+                    "content": [{ "attachment": {
+                        "contentType": "text/plain",
+                        "title": "report.txt",
+                        "size": 12,
+                    }}],
+                })],
+                Some(format!(
+                    "{base_url}/DocumentReference?subject=Patient/p1&page=2"
+                )),
+            ),
+            Some("2") => bundle(
+                // An R5 server, size is an integer64 serialized as a string
+                &[json!({
+                    "resourceType": "DocumentReference",
+                    "id": "d2",
+                    "description": "Scan",
+                    "subject": { "reference": "Patient/p1", "display": "Erika Mustermann" },
+                    "type": { "text": "Imaging" },
+                    "content": [{ "attachment": {
+                        "contentType": "application/dicom",
+                        "size": "34",
+                    }}],
+                })],
+                None,
+            ),
+            Some(page) => panic!("Unexpected page {page}"),
+        }
+    }
+
+    async fn patients(
+        State((_, requests)): State<MockState>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Json<serde_json::Value> {
+        requests.fetch_add(1, Ordering::SeqCst);
+        match query.get("page").map(String::as_str) {
+            None => bundle(
+                &[json!({ "resourceType": "Patient", "id": "p1" })],
+                Some("Patient?name=erika&page=2".to_string()),
+            ),
+            // Relative link back to the first page
+            _ => bundle(
+                &[json!({ "resourceType": "Patient", "id": "p2" })],
+                Some("Patient?name=erika".to_string()),
+            ),
+        }
+    }
+
+    async fn read_patient(
+        State((_, requests)): State<MockState>,
+        Path(id): Path<String>,
+    ) -> Json<serde_json::Value> {
+        requests.fetch_add(1, Ordering::SeqCst);
+        Json(json!({
+            "resourceType": "Patient",
+            "id": id,
+            "name": [{ "given": ["Erika", "Maria"], "family": "Mustermann" }],
+            "identifier": [{ "system": "urn:mrn", "value": "MRN-1" }],
+            "birthDate": "1964",
+            "gender": "female",
+        }))
+    }
+
+    #[test]
+    fn search_follows_next_links() {
+        with_server(|client, requests| async move {
+            let documents = client
+                .document_references(&[("subject", "Patient/p1")])
+                .await
+                .unwrap();
+            assert_eq!(requests.load(Ordering::SeqCst), 2);
+            let [r4, r5] = documents.as_slice() else {
+                panic!("Expected two DocumentReferences, got {}", documents.len());
+            };
+
+            assert_eq!(r4.id, "d1");
+            assert_eq!(r4.title, "report.txt");
+            assert_eq!(r4.subject, "Patient/p1");
+            assert_eq!(r4.kind, "report");
+            assert_eq!(r4.date, Some("2024-05-01T12:00:00Z".parse().unwrap()));
+            assert_eq!(r4.content_type, "text/plain");
+            assert_eq!(r4.size, Some(12));
+
+            assert_eq!(r5.id, "d2");
+            assert_eq!(r5.title, "Scan");
+            assert_eq!(r5.subject, "Erika Mustermann");
+            assert_eq!(r5.kind, "Imaging");
+            assert_eq!(r5.date, None);
+            assert_eq!(r5.size, Some(34));
+        });
+    }
+
+    #[test]
+    fn search_stops_at_link_to_fetched_page() {
+        with_server(|client, requests| async move {
+            let found = client
+                .search("Patient", &[("name", "erika")])
+                .await
+                .unwrap();
+            assert_eq!(requests.load(Ordering::SeqCst), 2);
+            let ids = found
+                .iter()
+                .map(|p| p["id"].as_str().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, ["p1", "p2"]);
+        });
+    }
+
+    #[test]
+    fn read_patient_joins_name_parts() {
+        with_server(|client, _| async move {
+            let patient = client.patient("p1").await.unwrap();
+            assert_eq!(patient.id, "p1");
+            assert_eq!(patient.name, "Erika Maria Mustermann");
+            assert_eq!(patient.identifiers, ["MRN-1"]);
+            assert_eq!(patient.birth_date, "1964");
+            assert_eq!(patient.gender, "female");
+        });
+    }
+
+    #[test]
+    fn malformed_patient_id_is_not_requested() {
+        with_server(|client, requests| async move {
+            for id in ["../Binary/x", "p1?_format=xml", "p1/_history/1", ".."] {
+                let error = client.patient(id).await.err().unwrap();
+                assert_eq!(error.to_string(), format!("Invalid FHIR id {id:?}"));
+            }
+            assert_eq!(requests.load(Ordering::SeqCst), 0);
+            client.patient("p1").await.unwrap();
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn ids_follow_the_fhir_grammar() {
+        for id in ["p1", "a-b.C", &"x".repeat(64)] {
+            assert!(check_id(id).is_ok(), "{id}");
+        }
+        for id in [
+            "",
+            "../Binary/x",
+            "x?_format=xml",
+            "a/b",
+            "a b",
+            "é",
+            &"x".repeat(65),
+        ] {
+            assert!(check_id(id).is_err(), "{id}");
+        }
+    }
+
+    #[test]
+    fn client_requires_base_url() {
+        let error = Client::new(&crate::server::Config::default())
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "No FHIR server configured in scout.toml");
+    }
+}
@@ -0,0 +1,632 @@
+#![cfg(feature = "server")]
+//! Reading the headers of genomics files without reading the data behind them
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::Path;
+
+use flate2::bufread::MultiGzDecoder;
+use itertools::Itertools;
+
+use crate::extract::MetadataExtractor;
+use crate::serverfn::{FieldInfo, FieldKind, FieldValue};
+
+/// Reads sampled from the start of FASTQ files
+const FASTQ_SAMPLE_READS: u64 = 10_000;
+
+/// Upper bound for header text, to stay within memory on corrupt files
+const MAX_HEADER_LEN: u64 = 64 << 20;
+
+/// Header lines of a VCF file
+#[derive(Default)]
+struct VcfHeader {
+    file_format: String,
+    reference: String,
+    contigs: Vec<String>,
+    samples: Vec<String>,
+}
+
+/// Statistics of the reads at the start of a FASTQ file
+struct FastqStats {
+    sampled_reads: u64,
+    /// Reads in the whole file, extrapolated from the size of the sample unless it is the whole file
+    estimated_reads: u64,
+    mean_length: f64,
+    /// Minimum, quartiles and maximum of the read lengths in the sample
+    length_quartiles: [u64; 5],
+}
+
+/// SAM header of a BAM or CRAM file
+#[derive(Default)]
+struct AlignmentHeader {
+    /// Assembly or URI of the reference sequences
+    reference: String,
+    /// Number of reference sequences
+    references: usize,
+    read_groups: Vec<String>,
+    /// Samples of the read groups, without duplicates
+    samples: Vec<String>,
+}
+
+fn text(name: &str, value: String) -> (String, FieldValue) {
+    (name.to_string(), FieldValue::Text(value))
+}
+
+fn number(name: &str, value: f64) -> (String, FieldValue) {
+    (name.to_string(), FieldValue::Number(value))
+}
+
+/// Header of .vcf, .vcf.gz and .vcf.bgz files
+pub struct VcfExtractor;
+
+impl MetadataExtractor for VcfExtractor {
+    fn matches(&self, mime_type: &str, name: &str) -> bool {
+        mime_type == "text/vcf" || name.ends_with(".vcf.gz") || name.ends_with(".vcf.bgz")
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        vec![
+            FieldInfo::new("VCF Version", FieldKind::Category),
+            FieldInfo::new("Reference", FieldKind::Category),
+            FieldInfo::new("Reference Sequences", FieldKind::Number),
+            FieldInfo::new("Samples", FieldKind::Text),
+        ]
+    }
+
+    fn extract(&self, path: &Path) -> Option<Vec<(String, FieldValue)>> {
+        let header = read_vcf_header(path)?;
+        Some(vec![
+            text("VCF Version", header.file_format),
+            text("Reference", header.reference),
+            number("Reference Sequences", header.contigs.len() as f64),
+            text("Samples", header.samples.join(", ")),
+        ])
+    }
+}
+
+/// Read statistics of .fastq and .fq files, optionally gzipped, from a sample of the first reads
+pub struct FastqExtractor;
+
+impl MetadataExtractor for FastqExtractor {
+    fn matches(&self, _mime_type: &str, name: &str) -> bool {
+        let name = name.strip_suffix(".gz").unwrap_or(name);
+        name.ends_with(".fastq") || name.ends_with(".fq")
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        vec![
+            FieldInfo::new("Reads", FieldKind::Number),
+            FieldInfo::new("Reads Sampled", FieldKind::Number),
+            FieldInfo::new("Mean Read Length", FieldKind::Number),
+            FieldInfo::new("Read Length Quartiles", FieldKind::Text),
+        ]
+    }
+
+    fn extract(&self, path: &Path) -> Option<Vec<(String, FieldValue)>> {
+        let stats = read_fastq_stats(path)?;
+        Some(vec![
+            number("Reads", stats.estimated_reads as f64),
+            number("Reads Sampled", stats.sampled_reads as f64),
+            number(
+                "Mean Read Length",
+                (stats.mean_length * 10.0).round() / 10.0,
+            ),
+            text(
+                "Read Length Quartiles",
+                stats.length_quartiles.iter().join(", "),
+            ),
+        ])
+    }
+}
+
+/// SAM header of .bam and .cram files
+pub struct AlignmentExtractor;
+
+impl MetadataExtractor for AlignmentExtractor {
+    fn matches(&self, _mime_type: &str, name: &str) -> bool {
+        name.ends_with(".bam") || name.ends_with(".cram")
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        vec![
+            FieldInfo::new("Reference", FieldKind::Category),
+            FieldInfo::new("Reference Sequences", FieldKind::Number),
+            FieldInfo::new("Read Groups", FieldKind::Text),
+            FieldInfo::new("Samples", FieldKind::Text),
+        ]
+    }
+
+    fn extract(&self, path: &Path) -> Option<Vec<(String, FieldValue)>> {
+        let header = read_alignment_header(path)?;
+        Some(vec![
+            text("Reference", header.reference),
+            number("Reference Sequences", header.references as f64),
+            text("Read Groups", header.read_groups.join(", ")),
+            text("Samples", header.samples.join(", ")),
+        ])
+    }
+}
+
+/// Whether a file is compressed with gzip or BGZF, going by its name
+fn is_gzip(name: &str) -> bool {
+    name.ends_with(".gz") || name.ends_with(".bgz")
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Header lines of a VCF file, up to the line with the column names
+fn read_vcf_header(path: &Path) -> Option<VcfHeader> {
+    let name = name(path);
+    let file = BufReader::new(File::open(path).ok()?);
+    let reader: Box<dyn BufRead> = if is_gzip(&name) {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
+    let mut header = VcfHeader::default();
+    for line in reader.take(MAX_HEADER_LEN).lines() {
+        let line = line.ok()?;
+        if let Some(file_format) = line.strip_prefix("##fileformat=") {
+            header.file_format = file_format.to_string();
+        } else if let Some(reference) = line.strip_prefix("##reference=") {
+            header.reference = reference.to_string();
+        } else if let Some(contig) = line.strip_prefix("##contig=<") {
+            let id = contig
+                .trim_end_matches('>')
+                .split(',')
+                .find_map(|field| field.strip_prefix("ID="));
+            header.contigs.extend(id.map(str::to_string));
+        } else if line.starts_with("#CHROM") {
+            // Samples follow the eight fixed columns and FORMAT
+            header.samples = line.split('\t').skip(9).map(str::to_string).collect();
+            return Some(header);
+        } else if !line.starts_with("##") {
+            break;
+        }
+    }
+    // A VCF file must have the column names
+    None
+}
+
+/// Statistics of the first reads of a FASTQ file
+fn read_fastq_stats(path: &Path) -> Option<FastqStats> {
+    let name = name(path);
+    let size = path.metadata().ok()?.len();
+    let mut file = BufReader::new(File::open(path).ok()?);
+    // Bytes of the file read for the sample, to extrapolate the number of reads
+    let (lengths, complete, consumed) = if is_gzip(&name) {
+        let mut reader = BufReader::new(MultiGzDecoder::new(file));
+        let (lengths, complete) = sample_fastq(&mut reader)?;
+        file = reader.into_inner().into_inner();
+        (lengths, complete, file.stream_position().ok()?)
+    } else {
+        let (lengths, complete) = sample_fastq(&mut file)?;
+        (lengths, complete, file.stream_position().ok()?)
+    };
+    if lengths.is_empty() {
+        return None;
+    }
+    let sampled_reads = lengths.len() as u64;
+    let estimated_reads = if complete || consumed == 0 {
+        sampled_reads
+    } else {
+        (sampled_reads as f64 * size as f64 / consumed as f64).round() as u64
+    };
+    let lengths = lengths.into_iter().sorted().collect::<Vec<_>>();
+    let quantile = |q: usize| lengths[(lengths.len() - 1) * q / 4];
+    Some(FastqStats {
+        sampled_reads,
+        estimated_reads,
+        mean_length: lengths.iter().sum::<u64>() as f64 / sampled_reads as f64,
+        length_quartiles: [0, 1, 2, 3, 4].map(quantile),
+    })
+}
+
+/// Lengths of the reads at the start of a FASTQ stream and whether the stream ended. Fails if the
+/// stream isn't FASTQ.
+fn sample_fastq(reader: &mut impl BufRead) -> Option<(Vec<u64>, bool)> {
+    let mut lengths = Vec::new();
+    let mut lines = reader.lines();
+    while (lengths.len() as u64) < FASTQ_SAMPLE_READS {
+        let Some(header) = lines.next() else {
+            return Some((lengths, true));
+        };
+        if !header.ok()?.starts_with('@') {
+            return None;
+        }
+        let sequence = lines.next()?.ok()?;
+        if !lines.next()?.ok()?.starts_with('+') {
+            return None;
+        }
+        lines.next()?.ok()?;
+        lengths.push(sequence.trim_end().len() as u64);
+    }
+    Some((lengths, false))
+}
+
+/// The SAM header and reference sequences of a BAM or CRAM file
+fn read_alignment_header(path: &Path) -> Option<AlignmentHeader> {
+    let (text, references) = if name(path).ends_with(".cram") {
+        let text = read_cram_header(path)?;
+        let references = text.lines().filter(|l| l.starts_with("@SQ\t")).count();
+        (text, references)
+    } else {
+        read_bam_header(path)?
+    };
+    let mut header = AlignmentHeader {
+        references,
+        ..Default::default()
+    };
+    for line in text.lines() {
+        let record = line.split('\t').next();
+        let field = |tag: &str| {
+            line.split('\t')
+                .skip(1)
+                .find_map(|field| field.strip_prefix(tag)?.strip_prefix(':'))
+                .map(str::to_string)
+        };
+        match record {
+            Some("@RG") => {
+                header.read_groups.extend(field("ID"));
+                if let Some(sample) = field("SM")
+                    && !header.samples.contains(&sample)
+                {
+                    header.samples.push(sample);
+                }
+            }
+            // All sequences normally come from the same assembly, so the first one is enough
+            Some("@SQ") if header.reference.is_empty() => {
+                header.reference = field("AS").or_else(|| field("UR")).unwrap_or_default();
+            }
+            _ => {}
+        }
+    }
+    Some(header)
+}
+
+/// SAM header text and the number of reference sequences of a BAM file
+fn read_bam_header(path: &Path) -> Option<(String, usize)> {
+    let mut reader = MultiGzDecoder::new(BufReader::new(File::open(path).ok()?));
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).ok()?;
+    if &magic != b"BAM\x01" {
+        return None;
+    }
+    let text = read_sized_text(&mut reader)?;
+    let references = read_i32(&mut reader)?;
+    Some((text, usize::try_from(references).ok()?))
+}
+
+/// SAM header text of a CRAM file, from the first block of its first container
+fn read_cram_header(path: &Path) -> Option<String> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    // Magic number, version and file id
+    let mut definition = [0; 26];
+    reader.read_exact(&mut definition).ok()?;
+    if &definition[..4] != b"CRAM" {
+        return None;
+    }
+    let major_version = definition[4];
+    // Container header: length, reference id, start, span, records, record counter, bases,
+    // blocks, landmarks and in version 3 a checksum
+    read_i32(&mut reader)?;
+    for _ in 0..4 {
+        read_itf8(&mut reader)?;
+    }
+    for _ in 0..2 {
+        if major_version >= 3 {
+            read_ltf8(&mut reader)?;
+        } else {
+            read_itf8(&mut reader)?;
+        }
+    }
+    read_itf8(&mut reader)?;
+    for _ in 0..read_itf8(&mut reader)? {
+        read_itf8(&mut reader)?;
+    }
+    if major_version >= 3 {
+        read_i32(&mut reader)?;
+    }
+    // Block header: compression method, content type, content id and sizes
+    let mut method_and_type = [0; 2];
+    reader.read_exact(&mut method_and_type).ok()?;
+    read_itf8(&mut reader)?;
+    let compressed_size = u64::try_from(read_itf8(&mut reader)?).ok()?;
+    read_itf8(&mut reader)?;
+    let mut block = reader.take(compressed_size.min(MAX_HEADER_LEN));
+    match method_and_type[0] {
+        0 => read_sized_text(&mut block),
+        1 => read_sized_text(&mut MultiGzDecoder::new(BufReader::new(block))),
+        // Other compression methods are rare for the header
+        _ => None,
+    }
+}
+
+/// Text preceded by its length as a little endian 32-bit integer
+fn read_sized_text(reader: &mut impl Read) -> Option<String> {
+    let len = u64::try_from(read_i32(reader)?).ok()?;
+    if len > MAX_HEADER_LEN {
+        return None;
+    }
+    let mut text = Vec::new();
+    reader.take(len).read_to_end(&mut text).ok()?;
+    Some(
+        String::from_utf8_lossy(&text)
+            .trim_end_matches('\0')
+            .to_string(),
+    )
+}
+
+fn read_i32(reader: &mut impl Read) -> Option<i32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes).ok()?;
+    Some(i32::from_le_bytes(bytes))
+}
+
+/// CRAM's variable length integer of up to 32 bits. The number of leading ones of the first byte
+/// is the number of bytes that follow.
+fn read_itf8(reader: &mut impl Read) -> Option<i32> {
+    let mut byte = [0; 1];
+    reader.read_exact(&mut byte).ok()?;
+    let first = byte[0];
+    let extra = first.leading_ones().min(4);
+    let mut value = u32::from(first)
+        & if extra == 4 {
+            0x0F
+        } else {
+            0xFF >> (extra + 1)
+        };
+    for i in 0..extra {
+        reader.read_exact(&mut byte).ok()?;
+        value = if i == 3 {
+            // The last byte of a five byte value only contributes its low four bits
+            (value << 4) | u32::from(byte[0] & 0x0F)
+        } else {
+            (value << 8) | u32::from(byte[0])
+        };
+    }
+    Some(value as i32)
+}
+
+/// CRAM's variable length integer of up to 64 bits, like ITF8 but with up to eight extra bytes
+fn read_ltf8(reader: &mut impl Read) -> Option<i64> {
+    let mut byte = [0; 1];
+    reader.read_exact(&mut byte).ok()?;
+    let first = byte[0];
+    let extra = first.leading_ones();
+    let mut value = u64::from(first) & (0xFF >> (extra + 1));
+    for _ in 0..extra {
+        reader.read_exact(&mut byte).ok()?;
+        value = (value << 8) | u64::from(byte[0]);
+    }
+    Some(value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use std::path::PathBuf;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    /// A file in the temporary directory, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("scout-{}-{name}", std::process::id()));
+            std::fs::write(&path, content).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn sized_text(text: &str) -> Vec<u8> {
+        [
+            (text.len() as i32).to_le_bytes().as_slice(),
+            text.as_bytes(),
+        ]
+        .concat()
+    }
+
+    const SAM_HEADER: &str = "@HD\tVN:1.6\tSO:coordinate\n\
+        @SQ\tSN:chr1\tLN:248956422\tAS:GRCh38\n\
+        @SQ\tSN:chr2\tLN:242193529\tAS:GRCh38\n\
+        @RG\tID:lane1\tSM:NA12878\n\
+        @RG\tID:lane2\tSM:NA12878\n";
+
+    #[test]
+    fn itf8() {
+        let cases: &[(&[u8], i32)] = &[
+            (&[0x00], 0),
+            (&[0x7F], 127),
+            (&[0x80, 0x80], 128),
+            (&[0xBF, 0xFF], 0x3FFF),
+            (&[0xC0, 0x40, 0x00], 0x4000),
+            (&[0xE0, 0x20, 0x00, 0x00], 0x20_0000),
+            (&[0xF1, 0x00, 0x00, 0x00, 0x00], 0x1000_0000),
+            (&[0xF1, 0x23, 0x45, 0x67, 0x08], 0x1234_5678),
+            // Only the low four bits of the fifth byte count
+            (&[0xF0, 0x00, 0x00, 0x00, 0xFF], 0x0F),
+            (&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F], -1),
+        ];
+        for (bytes, value) in cases {
+            assert_eq!(
+                read_itf8(&mut Cursor::new(bytes)),
+                Some(*value),
+                "{bytes:02X?}"
+            );
+        }
+        assert_eq!(read_itf8(&mut Cursor::new([0xE0, 0x00])), None);
+        assert_eq!(read_itf8(&mut Cursor::new([])), None);
+    }
+
+    #[test]
+    fn ltf8() {
+        let cases: &[(&[u8], i64)] = &[
+            (&[0x00], 0),
+            (&[0x7F], 127),
+            (&[0x80, 0xFF], 0xFF),
+            (&[0xC1, 0x00, 0x00], 0x1_0000),
+            (
+                &[0xFE, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07],
+                0x01_0203_0405_0607,
+            ),
+            (
+                &[0xFF, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+                0x0102_0304_0506_0708,
+            ),
+            (&[0xFF; 9], -1),
+        ];
+        for (bytes, value) in cases {
+            assert_eq!(
+                read_ltf8(&mut Cursor::new(bytes)),
+                Some(*value),
+                "{bytes:02X?}"
+            );
+        }
+        assert_eq!(read_ltf8(&mut Cursor::new([0xFF, 0x00])), None);
+    }
+
+    /// A CRAM file of the given major version whose first block holds the SAM header, compressed
+    /// with the given method
+    fn cram(major_version: u8, method: u8) -> Vec<u8> {
+        let mut data = b"CRAM".to_vec();
+        data.extend([major_version, 0]);
+        data.extend([0; 20]);
+        // Container length
+        data.extend(0i32.to_le_bytes());
+        // Reference id -1 as a five byte ITF8, start, span and records
+        data.extend([0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x00, 0x00, 0x00]);
+        // Record counter and bases, two byte LTF8 or ITF8
+        data.extend([0x80, 0x01, 0x80, 0x02]);
+        // One block and two landmarks
+        data.extend([0x01, 0x02, 0x80, 0x90, 0x00]);
+        if major_version >= 3 {
+            // CRC32
+            data.extend([0; 4]);
+        }
+        let content = sized_text(SAM_HEADER);
+        let content = if method == 1 { gzip(&content) } else { content };
+        // Method, file header content type and content id
+        data.extend([method, 0, 0]);
+        // Compressed and raw size as three byte ITF8
+        let size = |len: usize| [0xC0 | (len >> 16) as u8, (len >> 8) as u8, len as u8];
+        data.extend(size(content.len()));
+        data.extend(size(SAM_HEADER.len() + 4));
+        data.extend(content);
+        data
+    }
+
+    #[test]
+    fn cram_header() {
+        for (version, method) in [(3, 0), (3, 1), (2, 0), (2, 1)] {
+            let file = TempFile::new("header.cram", &cram(version, method));
+            assert_eq!(
+                read_cram_header(&file.0).as_deref(),
+                Some(SAM_HEADER),
+                "CRAM {version} with compression method {method}"
+            );
+        }
+        let file = TempFile::new("other.cram", &[b"BAM\x01".as_slice(), &[0; 40]].concat());
+        assert_eq!(read_cram_header(&file.0), None);
+    }
+
+    #[test]
+    fn bam_header() {
+        let mut data = b"BAM\x01".to_vec();
+        data.extend(sized_text(SAM_HEADER));
+        data.extend(2i32.to_le_bytes());
+        let file = TempFile::new("header.bam", &gzip(&data));
+        let (text, references) = read_bam_header(&file.0).unwrap();
+        assert_eq!(text, SAM_HEADER);
+        assert_eq!(references, 2);
+
+        let header = read_alignment_header(&file.0).unwrap();
+        assert_eq!(header.reference, "GRCh38");
+        assert_eq!(header.references, 2);
+        assert_eq!(header.read_groups, ["lane1", "lane2"]);
+        assert_eq!(header.samples, ["NA12878"]);
+
+        let file = TempFile::new("plain.bam", &data);
+        assert_eq!(read_bam_header(&file.0), None);
+    }
+
+    /// A FASTQ file of reads with pseudo-random bases and lengths from 50 to 149
+    fn fastq(reads: usize) -> Vec<u8> {
+        let mut state = 1u32;
+        let mut random = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            state >> 8
+        };
+        let mut data = Vec::new();
+        for i in 0..reads {
+            let len = 50 + random() as usize % 100;
+            let bases = (0..len)
+                .map(|_| b"ACGT"[random() as usize % 4] as char)
+                .collect::<String>();
+            writeln!(data, "@read{i}\n{bases}\n+\n{}", "I".repeat(len)).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn fastq_sample() {
+        let data = b"@r1\nACGT\n+\nIIII\n@r2\nACGTACGT\n+r2\nIIIIIIII\n";
+        assert_eq!(
+            sample_fastq(&mut Cursor::new(data)),
+            Some((vec![4, 8], true))
+        );
+        let gzipped = gzip(data);
+        let mut reader = BufReader::new(MultiGzDecoder::new(gzipped.as_slice()));
+        assert_eq!(sample_fastq(&mut reader), Some((vec![4, 8], true)));
+
+        assert_eq!(sample_fastq(&mut Cursor::new(b">r1\nACGT\n")), None);
+        assert_eq!(sample_fastq(&mut Cursor::new(b"@r1\nACGT\nIIII\n")), None);
+    }
+
+    #[test]
+    fn fastq_estimate() {
+        let reads = 3 * FASTQ_SAMPLE_READS as usize;
+        let data = fastq(reads);
+        let plain = TempFile::new("reads.fastq", &data);
+        let gzipped = TempFile::new("reads.fastq.gz", &gzip(&data));
+        for file in [&plain, &gzipped] {
+            let stats = read_fastq_stats(&file.0).unwrap();
+            assert_eq!(stats.sampled_reads, FASTQ_SAMPLE_READS);
+            let error = stats.estimated_reads.abs_diff(reads as u64) as f64 / reads as f64;
+            assert!(
+                error < 0.05,
+                "{} reads estimated for {reads} in {}",
+                stats.estimated_reads,
+                file.0.display()
+            );
+            assert!((95.0..105.0).contains(&stats.mean_length));
+            assert_eq!(stats.length_quartiles[0], 50);
+            assert_eq!(stats.length_quartiles[4], 149);
+        }
+
+        // A file shorter than the sample is counted exactly
+        let small = TempFile::new("small.fq", &fastq(10));
+        let stats = read_fastq_stats(&small.0).unwrap();
+        assert_eq!(stats.sampled_reads, 10);
+        assert_eq!(stats.estimated_reads, 10);
+    }
+}
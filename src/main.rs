@@ -1,5 +1,13 @@
 use dioxus::prelude::*;
 
+mod archive;
+mod checksum;
+mod dicom;
+mod download;
+mod export;
+mod extract;
+mod fhir;
+mod genomics;
 mod server;
 mod serverfn;
 mod table;
@@ -8,7 +16,21 @@ mod table;
 #[rustfmt::skip]
 enum Route {
     #[route("/")]
-    FileTable {}
+    FileTable {},
+    #[route("/files/:..segments")]
+    Directory { segments: Vec<String> },
+    #[route("/split")]
+    SplitView {},
+    #[route("/tree")]
+    FileTree {},
+    #[route("/documents")]
+    DocumentReferences {},
+    #[route("/patients")]
+    PatientSearch {},
+    #[route("/patient/:id")]
+    PatientFiles { id: String },
+    #[route("/duplicates")]
+    Duplicates {},
 }
 
 const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
@@ -16,12 +38,31 @@ const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
 fn main() {
     dioxus::logger::initialize_default();
 
-    // #[cfg(feature = "server")]
-    // if let Err(e) = server::load_config() {
-    //     tracing::error!("Failed to load config: {e}");
-    //     std::process::exit(1);
-    // }
+    #[cfg(feature = "server")]
+    {
+        if let Err(e) = server::load_config() {
+            tracing::error!("Failed to load config: {e}");
+            std::process::exit(1);
+        }
+        // Files, archives and large table exports are streamed by plain routes, as server functions
+        // can't be downloaded directly
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let address = dioxus::cli_config::fullstack_address_or_localhost();
+                let router = axum::Router::new()
+                    .route("/archive", axum::routing::get(archive::download))
+                    .route("/file", axum::routing::get(download::file))
+                    .route("/export", axum::routing::get(download::table))
+                    .serve_dioxus_application(ServeConfig::new().unwrap(), App);
+                let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+                axum::serve(listener, router.into_make_service())
+                    .await
+                    .unwrap();
+            });
+    }
 
+    #[cfg(not(feature = "server"))]
     dioxus::launch(App);
 }
 
@@ -31,6 +72,26 @@ pub fn format_timestamp(timestamp: jiff::Timestamp) -> String {
     zoned.strftime("%b %d, %Y, %H:%M %Z").to_string()
 }
 
+pub fn format_date(date: jiff::civil::Date) -> String {
+    // Jan 08, 2020
+    date.strftime("%b %d, %Y").to_string()
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
 #[component]
 fn App() -> Element {
     // Load polyfill for CSS anchor positioning if needed (https://github.com/oddbird/css-anchor-positioning)
@@ -46,21 +107,1153 @@ fn App() -> Element {
 
 #[component]
 fn FileTable() -> Element {
-    let files = use_server_future(|| serverfn::get_files())?;
-    match &*files.read_unchecked() {
+    rsx! {
+        FilePane { id: "files", path: "" }
+    }
+}
+
+/// The files in a directory below the root directory
+#[component]
+fn Directory(segments: ReadOnlySignal<Vec<String>>) -> Element {
+    rsx! {
+        FilePane { id: "files", path: segments.read().join("/") }
+    }
+}
+
+/// Route listing the files of a directory, given as a path relative to the root directory
+fn directory_route(path: &str) -> Route {
+    let segments = path
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if segments.is_empty() {
+        Route::FileTable {}
+    } else {
+        Route::Directory { segments }
+    }
+}
+
+/// Two directories side by side. Files are moved by dragging rows from one pane to the other.
+#[component]
+fn SplitView() -> Element {
+    let mut paths = use_signal(|| [String::new(), String::new()]);
+    // Incremented after moving files so both panes reload
+    let mut generation = use_signal(|| 0usize);
+    let directories = use_server_future(|| serverfn::get_files(String::new()))?;
+    let directories = match &*directories.read_unchecked() {
+        Some(Ok(files)) => files
+            .iter()
+            .filter(|f| f.kind == "Directory")
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+    rsx! {
+        div { class: "grid grid-cols-2",
+            for pane in 0..2 {
+                div { key: "{pane}", class: "min-w-0",
+                    select {
+                        class: "m-4 mb-0 border border-gray-300 rounded p-1",
+                        "aria-label": "Directory",
+                        onchange: move |event: Event<FormData>| paths.with_mut(|paths| paths[pane] = event.value()),
+                        option { value: "", selected: paths()[pane].is_empty(), "/" }
+                        for directory in directories.iter() {
+                            option {
+                                key: "{directory}",
+                                value: "{directory}",
+                                selected: &paths()[pane] == directory,
+                                "/{directory}"
+                            }
+                        }
+                    }
+                    FilePane {
+                        id: "pane-{pane}",
+                        path: paths()[pane].clone(),
+                        generation: generation(),
+                        onrowsdrop: move |drop: table::RowsDrop| async move {
+                            let names = drop.values.into_iter().map(|row| row[0].clone()).collect();
+                            let from = paths()[1 - pane].clone();
+                            let to = paths()[pane].clone();
+                            if let Err(e) = serverfn::move_files(names, from, to).await {
+                                tracing::error!("Failed to move files: {e:#}");
+                            }
+                            generation += 1;
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Table of the files in a directory, given as a path relative to the root directory
+#[component]
+fn FilePane(
+    id: String,
+    path: ReadOnlySignal<String>,
+    /// Reloads the files when changed
+    #[props(default)]
+    generation: ReadOnlySignal<usize>,
+    onrowsdrop: Option<EventHandler<table::RowsDrop>>,
+) -> Element {
+    let mut files_resource = use_server_future(move || {
+        generation();
+        serverfn::get_files(path())
+    })?;
+    // Path of a file in this directory as shown to the user
+    let full_path = move |name: &str| {
+        if path.read().is_empty() {
+            format!("/{name}")
+        } else {
+            format!("/{path}/{name}")
+        }
+    };
+    // Ask for a patient and publish files of this directory to the FHIR server
+    let publish = move |names: Vec<String>| async move {
+        if names.is_empty() {
+            return;
+        }
+        let prompt = document::eval("return prompt('Publish to FHIR for Patient id');");
+        let Ok(Some(patient_id)) = prompt.join::<Option<String>>().await else {
+            return;
+        };
+        let patient_id = patient_id.trim().to_string();
+        if patient_id.is_empty() {
+            return;
+        }
+        if let Err(e) = serverfn::publish_files(path(), names, patient_id).await {
+            tracing::error!("Failed to publish files: {e:#}");
+        }
+        files_resource.restart();
+    };
+    // Hash files of this directory again and list them once done
+    let verify = move |names: Vec<String>| async move {
+        if names.is_empty() {
+            return;
+        }
+        if let Err(e) = serverfn::verify_checksums(path(), names).await {
+            tracing::error!("Failed to verify files: {e:#}");
+        }
+        files_resource.restart();
+    };
+    let mut views_resource = use_server_future(serverfn::get_views)?;
+    let views = match &*views_resource.read_unchecked() {
+        Some(Ok(views)) => views.clone(),
+        Some(Err(e)) => {
+            tracing::error!("Failed to load views: {e:#}");
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    let fields_resource = use_server_future(serverfn::get_metadata_fields)?;
+    let fields = match &*fields_resource.read_unchecked() {
+        Some(Ok(fields)) => fields.clone(),
+        Some(Err(e)) => {
+            tracing::error!("Failed to load metadata fields: {e:#}");
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    let mut custom_fields_resource = use_server_future(serverfn::get_custom_fields)?;
+    let custom_fields = match &*custom_fields_resource.read_unchecked() {
+        Some(Ok(fields)) => fields.clone(),
+        Some(Err(e)) => {
+            tracing::error!("Failed to load custom fields: {e:#}");
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    // List the files again once the fields still being read are ready
+    use_effect(move || {
+        let pending = matches!(
+            &*files_resource.read(),
+            Some(Ok(files)) if files.iter().any(|f| f.fields_pending || f.checksum_pending)
+        );
+        if pending {
+            spawn(async move {
+                match serverfn::wait_for_metadata(path()).await {
+                    Ok(()) => files_resource.restart(),
+                    Err(e) => tracing::error!("Failed to wait for metadata: {e:#}"),
+                }
+            });
+        }
+    });
+    match &*files_resource.read_unchecked() {
         Some(Ok(files)) => rsx! {
             table::Table {
-                columns: vec![table::Column::new("Name")],
-                data: files.iter().map(|f| vec![f.name.clone()]).collect(),
+                id,
+                columns: file_pane_columns(
+                    file_name_column(files)
+                        .editable()
+                        .validate(|name: String| {
+                            if name.trim().is_empty() {
+                                Err("Name must not be empty".to_string())
+                            } else if name.contains(['/', '\\']) {
+                                Err("Name must not contain slashes".to_string())
+                            } else {
+                                Ok(())
+                            }
+                        }),
+                    &fields,
+                    &custom_fields,
+                ),
+                footer: true,
+                data: files
+                    .iter()
+                    .map(|file| file_pane_row(file, &fields, &custom_fields))
+                    .collect::<Vec<_>>(),
                 ondetail: {
                     let files = files.clone();
                     move |id: usize| {
                         tracing::info!("User clicked detail for file: {}", files[id].name)
                     }
                 },
+                row_actions: vec![
+                    table::RowAction::new("Open", {
+                        let files = files.clone();
+                        move |id: usize| {
+                            let file = &files[id];
+                            if file.kind == "Directory" {
+                                navigator().push(directory_route(&format!("{path}/{}", file.name)));
+                            } else {
+                                export::file_link(export::FileLink::Open, &path(), &file.name);
+                            }
+                        }
+                    }),
+                    table::RowAction::new("Download", {
+                        let files = files.clone();
+                        move |id: usize| export::file_link(export::FileLink::Download, &path(), &files[id].name)
+                    })
+                    .enabled({
+                        let files = files.clone();
+                        move |id: usize| files[id].kind != "Directory"
+                    }),
+                    table::RowAction::new("Rename", {
+                        let files = files.clone();
+                        move |id: usize| {
+                            let name = files[id].name.clone();
+                            async move {
+                                let prompt = document::eval(
+                                    "return prompt('Rename to', await dioxus.recv());",
+                                );
+                                let _ = prompt.send(&name);
+                                let Ok(Some(new_name)) = prompt.join::<Option<String>>().await else {
+                                    return;
+                                };
+                                if new_name.is_empty() || new_name == name {
+                                    return;
+                                }
+                                if let Err(e) = serverfn::rename_file(path(), name, new_name).await {
+                                    tracing::error!("Failed to rename file: {e:#}");
+                                }
+                                files_resource.restart();
+                            }
+                        }
+                    }),
+                    table::RowAction::new("Publish to FHIR", {
+                        let files = files.clone();
+                        move |id: usize| publish(vec![files[id].name.clone()])
+                    })
+                    .enabled({
+                        let files = files.clone();
+                        move |id: usize| files[id].kind != "Directory"
+                    }),
+                    table::RowAction::new("Set Field", {
+                        let files = files.clone();
+                        move |id: usize| {
+                            let name = files[id].name.clone();
+                            async move {
+                                let prompt = document::eval("return prompt('Field name');");
+                                let Ok(Some(field)) = prompt.join::<Option<String>>().await else {
+                                    return;
+                                };
+                                if field.trim().is_empty() {
+                                    return;
+                                }
+                                let prompt = document::eval(
+                                    "return prompt('Value of ' + await dioxus.recv());",
+                                );
+                                let _ = prompt.send(&field);
+                                let Ok(Some(value)) = prompt.join::<Option<String>>().await else {
+                                    return;
+                                };
+                                let value = (!value.trim().is_empty())
+                                    .then(|| infer_field_value(value.trim()));
+                                if let Err(e) = serverfn::set_custom_field(path(), name, field, value).await {
+                                    tracing::error!("Failed to set field: {e:#}");
+                                }
+                                files_resource.restart();
+                                custom_fields_resource.restart();
+                            }
+                        }
+                    }),
+                    table::RowAction::new("Verify Integrity", {
+                        let files = files.clone();
+                        move |id: usize| verify(vec![files[id].name.clone()])
+                    })
+                    .enabled({
+                        let files = files.clone();
+                        move |id: usize| files[id].kind != "Directory"
+                    }),
+                    table::RowAction::new("Copy Path", {
+                        let files = files.clone();
+                        move |id: usize| {
+                            let clipboard = document::eval(
+                                "navigator.clipboard.writeText(await dioxus.recv())",
+                            );
+                            let _ = clipboard.send(full_path(&files[id].name));
+                        }
+                    }),
+                    table::RowAction::new("Copy Share Link", {
+                        let files = files.clone();
+                        move |id: usize| export::file_link(export::FileLink::Copy, &path(), &files[id].name)
+                    })
+                    .enabled({
+                        let files = files.clone();
+                        move |id: usize| files[id].kind != "Directory"
+                    }),
+                ],
+                selection_actions: vec![
+                    table::SelectionAction::new("Publish to FHIR", {
+                        let files = files.clone();
+                        move |ids: Vec<usize>| {
+                            publish(
+                                ids.into_iter()
+                                    .filter(|&id| files[id].kind != "Directory")
+                                    .map(|id| files[id].name.clone())
+                                    .collect(),
+                            )
+                        }
+                    }),
+                    table::SelectionAction::new("Verify Integrity", {
+                        let files = files.clone();
+                        move |ids: Vec<usize>| {
+                            verify(
+                                ids.into_iter()
+                                    .filter(|&id| files[id].kind != "Directory")
+                                    .map(|id| files[id].name.clone())
+                                    .collect(),
+                            )
+                        }
+                    }),
+                    table::SelectionAction::new("Export BagIt", {
+                        let files = files.clone();
+                        move |ids: Vec<usize>| {
+                            let names = ids.iter().map(|&id| files[id].name.clone()).collect::<Vec<_>>();
+                            export::download_archive(export::ArchiveFormat::BagIt, &path(), &names)
+                        }
+                    }),
+                    table::SelectionAction::new("Export RO-Crate", {
+                        let files = files.clone();
+                        move |ids: Vec<usize>| {
+                            let names = ids.iter().map(|&id| files[id].name.clone()).collect::<Vec<_>>();
+                            export::download_archive(export::ArchiveFormat::RoCrate, &path(), &names)
+                        }
+                    }),
+                    table::SelectionAction::new("Add Tag", {
+                        let files = files.clone();
+                        move |ids: Vec<usize>| {
+                            let names = ids
+                                .into_iter()
+                                .map(|id| files[id].name.clone())
+                                .collect::<Vec<_>>();
+                            async move {
+                                let prompt = document::eval("return prompt('Tag');");
+                                let Ok(Some(tag)) = prompt.join::<Option<String>>().await else {
+                                    return;
+                                };
+                                let tag = tag.trim().to_string();
+                                if tag.is_empty() {
+                                    return;
+                                }
+                                if let Err(e) = serverfn::add_tag(path(), names, tag).await {
+                                    tracing::error!("Failed to add tag: {e:#}");
+                                }
+                                files_resource.restart();
+                            }
+                        }
+                    }),
+                ],
+                row_details: {
+                    let files = files.clone();
+                    move |id: usize| {
+                        let file = &files[id];
+                        let full_path = full_path(&file.name);
+                        rsx! {
+                            dl { class: "grid grid-cols-[max-content_1fr] gap-x-4 text-sm",
+                                dt { class: "text-gray-500", "Path" }
+                                dd { "{full_path}" }
+                                dt { class: "text-gray-500", "Type" }
+                                dd { "{file.kind}" }
+                                dt { class: "text-gray-500", "Size" }
+                                dd { "{format_bytes(file.size)} ({file.size} bytes)" }
+                                dt { class: "text-gray-500", "Modified" }
+                                dd { "{format_timestamp(file.modified)}" }
+                                for (name , value) in file.fields.iter() {
+                                    dt { class: "text-gray-500", "{name}" }
+                                    dd {
+                                        match value {
+                                            serverfn::FieldValue::Date(date) => format_date(*date),
+                                            value => value.to_string(),
+                                        }
+                                    }
+                                }
+                                if !file.metadata.tags.is_empty() {
+                                    dt { class: "text-gray-500", "Tags" }
+                                    dd { {itertools::Itertools::join(&mut file.metadata.tags.iter(), ", ")} }
+                                }
+                                if !file.metadata.notes.is_empty() {
+                                    dt { class: "text-gray-500", "Notes" }
+                                    dd { class: "whitespace-pre-wrap", "{file.metadata.notes}" }
+                                }
+                                for (name , value) in file.metadata.custom_fields.iter() {
+                                    dt { class: "text-gray-500", "{name}" }
+                                    dd {
+                                        match value {
+                                            serverfn::FieldValue::Date(date) => format_date(*date),
+                                            value => value.to_string(),
+                                        }
+                                    }
+                                }
+                                if let Some(checksum) = &file.metadata.checksum {
+                                    dt { class: "text-gray-500", "SHA-256" }
+                                    dd { class: "font-mono break-all", "{checksum.sha256}" }
+                                    if let Some(md5) = &checksum.md5 {
+                                        dt { class: "text-gray-500", "MD5" }
+                                        dd { class: "font-mono break-all", "{md5}" }
+                                    }
+                                    if let Some(mismatch) = &checksum.mismatch {
+                                        dt { class: "text-red-600", "Integrity" }
+                                        dd { class: "text-red-600 break-all",
+                                            "Content changed without a change of size or modification time, SHA-256 is now {mismatch}"
+                                        }
+                                    } else if let Some(verified) = checksum.verified {
+                                        dt { class: "text-gray-500", "Verified" }
+                                        dd { "{format_timestamp(verified)}" }
+                                    }
+                                }
+                                if let Some(fhir) = &file.metadata.fhir {
+                                    dt { class: "text-gray-500", "Patient" }
+                                    dd { "Patient/{fhir.patient_id}" }
+                                    dt { class: "text-gray-500", "FHIR Resources" }
+                                    dd { "DocumentReference/{fhir.document_reference_id}, Binary/{fhir.binary_id}" }
+                                    dt { class: "text-gray-500", "Published" }
+                                    dd { "{format_timestamp(fhir.published)}" }
+                                }
+                            }
+                        }
+                    }
+                },
+                oncelledit: {
+                    let files = files.clone();
+                    move |edit: table::CellEdit| -> table::EditResult {
+                        let name = files[edit.row].name.clone();
+                        let kind = custom_fields
+                            .iter()
+                            .find(|field| field.name == edit.column)
+                            .map(|field| field.kind);
+                        Box::pin(async move {
+                            match (edit.column.as_str(), kind) {
+                                ("Name", _) => {
+                                    let result = serverfn::rename_file(path(), edit.old_value, edit.new_value).await;
+                                    // Actions and edits of the row refer to the file by its name
+                                    if result.is_ok() {
+                                        files_resource.restart();
+                                    }
+                                    result
+                                }
+                                ("Tags", _) => {
+                                    let tags = edit
+                                        .new_value
+                                        .split(',')
+                                        .map(str::trim)
+                                        .filter(|tag| !tag.is_empty())
+                                        .map(str::to_string)
+                                        .collect();
+                                    serverfn::set_tags(path(), name, tags).await
+                                }
+                                ("Notes", _) => serverfn::set_notes(path(), name, edit.new_value).await,
+                                (field, Some(kind)) => {
+                                    let value = parse_field_value(kind, &edit.new_value)?;
+                                    serverfn::set_custom_field(path(), name, field.to_string(), value).await
+                                }
+                                (field, None) => return Err(format!("{field} can't be edited")),
+                            }
+                            .map_err(|e| format!("{e:#}"))
+                        })
+                    }
+                },
+                draggable_rows: onrowsdrop.is_some(),
+                onrowsdrop,
+                views,
+                onsaveview: move |view| async move {
+                    if let Err(e) = serverfn::save_view(view).await {
+                        tracing::error!("Failed to save view: {e:#}");
+                    }
+                    views_resource.restart();
+                },
+                ondeleteview: move |name| async move {
+                    if let Err(e) = serverfn::delete_view(name).await {
+                        tracing::error!("Failed to delete view: {e:#}");
+                    }
+                    views_resource.restart();
+                },
+                server_export: move |(format, view): (export::ExportFormat, table::View)| {
+                    export::download_table(format, &path(), &view)
+                },
+            }
+        },
+        Some(Err(e)) => rsx! { "Error loading files: {e:#}" },
+        None => rsx! { "Loading..." },
+    }
+}
+
+/// Name column of a file table showing a folder or file icon before the name
+fn file_name_column(files: &[serverfn::File]) -> table::Column {
+    let is_dir = files
+        .iter()
+        .map(|f| f.kind == "Directory")
+        .collect::<Vec<_>>();
+    table::Column::new("Name").render(move |cell: table::Cell| {
+        rsx! {
+            span { class: "flex items-center gap-1",
+                svg {
+                    class: "size-5 shrink-0 text-gray-500",
+                    fill: "currentColor",
+                    "viewBox": "0 -960 960 960",
+                    xmlns: "http://www.w3.org/2000/svg",
+                    if is_dir[cell.row] {
+                        path { d: "M160-160q-33 0-56.5-23.5T80-240v-480q0-33 23.5-56.5T160-800h240l80 80h320q33 0 56.5 23.5T880-640v400q0 33-23.5 56.5T800-160z" }
+                    } else {
+                        path { d: "M240-80q-33 0-56.5-23.5T160-160v-640q0-33 23.5-56.5T240-880h320l240 240v480q0 33-23.5 56.5T720-80zm280-520h200L520-800z" }
+                    }
+                }
+                table::Highlight { text: cell.value, positions: cell.highlights }
             }
+        }
+    })
+}
+
+/// Columns of `FilePane` after the name column. Also used to export large directories on the
+/// server, where the name column can't be rendered.
+fn file_pane_columns(
+    name_column: table::Column,
+    fields: &[serverfn::FieldInfo],
+    custom_fields: &[serverfn::FieldInfo],
+) -> Vec<table::Column> {
+    [
+        name_column,
+        table::Column::new("Type")
+            .categorical()
+            .collation(table::Collation::Locale),
+        table::Column::new("Size").bytes(),
+        table::Column::new("Modified").date(),
+        table::Column::new("FHIR").categorical(),
+        table::Column::new("Tags").categorical().list().editable(),
+        table::Column::new("Notes").hidden().editable(),
+        table::Column::new("SHA-256")
+            .hidden()
+            .collation(table::Collation::Binary),
+        table::Column::new("MD5")
+            .hidden()
+            .collation(table::Collation::Binary),
+        table::Column::new("Integrity").categorical(),
+    ]
+    .into_iter()
+    .chain(fields.iter().map(field_column))
+    .chain(
+        custom_fields
+            .iter()
+            .map(|field| field_column(field).editable()),
+    )
+    .collect()
+}
+
+/// Cells of a file in the columns of `FilePane`
+fn file_pane_row(
+    file: &serverfn::File,
+    fields: &[serverfn::FieldInfo],
+    custom_fields: &[serverfn::FieldInfo],
+) -> Vec<String> {
+    let mut row = file_row(file);
+    row.push(
+        if file.metadata.fhir.is_some() {
+            "Published"
+        } else {
+            ""
+        }
+        .to_string(),
+    );
+    row.push(itertools::Itertools::join(
+        &mut file.metadata.tags.iter(),
+        ", ",
+    ));
+    row.push(file.metadata.notes.clone());
+    row.extend(checksum_cells(file));
+    row.extend(fields.iter().map(|field| field_cell(file, field)));
+    row.extend(custom_fields.iter().map(|field| {
+        file.metadata
+            .custom_fields
+            .get(&field.name)
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+    }));
+    row
+}
+
+/// Cells of a file in a file table, matching the columns of `FilePane`
+fn file_row(file: &serverfn::File) -> Vec<String> {
+    vec![
+        file.name.clone(),
+        file.kind.clone(),
+        file.size.to_string(),
+        file.modified.to_string(),
+    ]
+}
+
+/// Cells of the SHA-256, MD5 and Integrity columns of a file table
+fn checksum_cells(file: &serverfn::File) -> [String; 3] {
+    let Some(checksum) = &file.metadata.checksum else {
+        return Default::default();
+    };
+    let integrity = if checksum.mismatch.is_some() {
+        "Changed"
+    } else if checksum.verified.is_some() {
+        "Verified"
+    } else {
+        "Not verified"
+    };
+    [
+        checksum.sha256.clone(),
+        checksum.md5.clone().unwrap_or_default(),
+        integrity.to_string(),
+    ]
+}
+
+/// Hidden column of a field read by metadata extractors
+fn field_column(field: &serverfn::FieldInfo) -> table::Column {
+    let column = table::Column::new(&field.name).hidden();
+    match field.kind {
+        serverfn::FieldKind::Text => column,
+        serverfn::FieldKind::Category => column.categorical(),
+        serverfn::FieldKind::Number => column.numeric(),
+        serverfn::FieldKind::Date => column.date(),
+    }
+}
+
+/// Cell of a field column, empty if the file doesn't have the field
+fn field_cell(file: &serverfn::File, field: &serverfn::FieldInfo) -> String {
+    file.fields
+        .iter()
+        .find(|(name, _)| *name == field.name)
+        .map(|(_, value)| value.to_string())
+        .unwrap_or_default()
+}
+
+/// Value of a custom field as entered in a cell, or `None` to remove the field if empty
+fn parse_field_value(
+    kind: serverfn::FieldKind,
+    text: &str,
+) -> Result<Option<serverfn::FieldValue>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(match kind {
+        serverfn::FieldKind::Text | serverfn::FieldKind::Category => {
+            serverfn::FieldValue::Text(text.to_string())
+        }
+        serverfn::FieldKind::Number => serverfn::FieldValue::Number(
+            parse_number(text).ok_or_else(|| "Expected a number".to_string())?,
+        ),
+        serverfn::FieldKind::Date => serverfn::FieldValue::Date(
+            text.parse()
+                .map_err(|_| "Expected a date like 2024-12-31".to_string())?,
+        ),
+    }))
+}
+
+/// Value of a new custom field, typed as a number or date if it looks like one
+fn infer_field_value(text: &str) -> serverfn::FieldValue {
+    if let Some(number) = parse_number(text) {
+        serverfn::FieldValue::Number(number)
+    } else if let Ok(date) = text.parse() {
+        serverfn::FieldValue::Date(date)
+    } else {
+        serverfn::FieldValue::Text(text.to_string())
+    }
+}
+
+/// A finite number. "NaN" and "inf" parse as floats, but are kept as text.
+fn parse_number(text: &str) -> Option<f64> {
+    text.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// All files below the root directory as a tree. Directories load their files when first expanded.
+#[component]
+fn FileTree() -> Element {
+    let root = use_server_future(|| serverfn::get_files(String::new()))?;
+    match &*root.read_unchecked() {
+        Some(Ok(files)) => rsx! {
+            FileTreeTable { root: files.clone() }
         },
         Some(Err(e)) => rsx! { "Error loading files: {e:#}" },
         None => rsx! { "Loading..." },
     }
 }
+
+#[component]
+fn FileTreeTable(root: Vec<serverfn::File>) -> Element {
+    // Files loaded so far with the path of their directory, in the order they were loaded
+    let mut files = use_signal(|| {
+        root.iter()
+            .map(|f| (String::new(), f.clone()))
+            .collect::<Vec<_>>()
+    });
+    let mut tree = use_signal(|| {
+        root.iter()
+            .map(|f| table::TreeNode {
+                parent: None,
+                expandable: f.kind == "Directory",
+            })
+            .collect::<Vec<_>>()
+    });
+    let loaded = files
+        .read()
+        .iter()
+        .map(|(_, f)| f.clone())
+        .collect::<Vec<_>>();
+    rsx! {
+        table::Table {
+            id: "tree",
+            columns: vec![
+                file_name_column(&loaded),
+                table::Column::new("Type")
+                    .categorical()
+                    .collation(table::Collation::Locale),
+                table::Column::new("Size").bytes(),
+                table::Column::new("Modified").date(),
+            ],
+            data: loaded.iter().map(file_row).collect::<Vec<_>>(),
+            tree: tree(),
+            onexpandrow: move |id: usize| -> table::LoadResult {
+                Box::pin(async move {
+                    let (dir, file) = files.read()[id].clone();
+                    let path = if dir.is_empty() {
+                        file.name
+                    } else {
+                        format!("{dir}/{}", file.name)
+                    };
+                    let children = serverfn::get_files(path.clone())
+                        .await
+                        .map_err(|e| format!("{e:#}"))?;
+                    tree.write()
+                        .extend(
+                            children
+                                .iter()
+                                .map(|f| table::TreeNode {
+                                    parent: Some(id),
+                                    expandable: f.kind == "Directory",
+                                }),
+                        );
+                    files.write().extend(children.into_iter().map(|f| (path.clone(), f)));
+                    Ok(())
+                })
+            },
+            ondetail: move |id: usize| {
+                tracing::info!("User clicked detail for file: {}", files.read()[id].1.name)
+            },
+            row_actions: vec![
+                table::RowAction::new("Show in Parent Folder", move |id: usize| {
+                    navigator().push(directory_route(&files.read()[id].0));
+                }),
+            ],
+        }
+    }
+}
+
+/// DocumentReferences on the configured FHIR server
+#[component]
+fn DocumentReferences() -> Element {
+    let documents = use_server_future(serverfn::get_document_references)?;
+    match &*documents.read_unchecked() {
+        Some(Ok(documents)) => rsx! {
+            table::Table {
+                id: "documents",
+                columns: vec![
+                    table::Column::new("Title"),
+                    table::Column::new("Subject"),
+                    table::Column::new("Type").categorical(),
+                    table::Column::new("Date").date(),
+                    table::Column::new("Content Type").categorical(),
+                    table::Column::new("Size").bytes(),
+                ],
+                data: documents
+                    .iter()
+                    .map(|d| {
+                        vec![
+                            d.title.clone(),
+                            d.subject.clone(),
+                            d.kind.clone(),
+                            d.date.map(|date| date.to_string()).unwrap_or_default(),
+                            d.content_type.clone(),
+                            d.size.map(|size| size.to_string()).unwrap_or_default(),
+                        ]
+                    })
+                    .collect::<Vec<_>>(),
+                ondetail: |_| {},
+            }
+        },
+        Some(Err(e)) => rsx! { "Error loading documents: {e:#}" },
+        None => rsx! { "Loading..." },
+    }
+}
+
+/// Search for Patients on the FHIR server by identifier or name
+#[component]
+fn PatientSearch() -> Element {
+    let mut query = use_signal(String::new);
+    let mut input = use_signal(String::new);
+    let patients = use_server_future(move || serverfn::search_patients(query()))?;
+    rsx! {
+        form {
+            class: "m-4 flex items-center gap-2",
+            onsubmit: move |event| {
+                event.prevent_default();
+                query.set(input());
+            },
+            input {
+                class: "border border-gray-300 rounded p-1",
+                "aria-label": "Identifier or name",
+                placeholder: "Identifier or name",
+                value: "{input}",
+                oninput: move |event| input.set(event.value()),
+            }
+            button {
+                class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                r#type: "submit",
+                "Search"
+            }
+        }
+        match &*patients.read_unchecked() {
+            Some(Ok(patients)) => rsx! {
+                table::Table {
+                    id: "patients",
+                    columns: vec![
+                        table::Column::new("Name"),
+                        table::Column::new("Identifier"),
+                        table::Column::new("Birth Date"),
+                        table::Column::new("Gender").categorical(),
+                    ],
+                    data: patients
+                        .iter()
+                        .map(|p| {
+                            vec![
+                                p.name.clone(),
+                                p.identifiers.join(", "),
+                                p.birth_date.clone(),
+                                p.gender.clone(),
+                            ]
+                        })
+                        .collect::<Vec<_>>(),
+                    ondetail: {
+                        let patients = patients.clone();
+                        move |id: usize| {
+                            navigator()
+                                .push(Route::PatientFiles {
+                                    id: patients[id].id.clone(),
+                                });
+                        }
+                    },
+                }
+            },
+            Some(Err(e)) => rsx! { "Error searching patients: {e:#}" },
+            None => rsx! { "Loading..." },
+        }
+    }
+}
+
+/// A Patient on the FHIR server with the local files published for it and all DocumentReferences
+/// about it. Published files are shown once, with the source "Local and FHIR".
+#[component]
+fn PatientFiles(id: ReadOnlySignal<String>) -> Element {
+    let patient_files = use_server_future(move || serverfn::get_patient_files(id()))?;
+    match &*patient_files.read_unchecked() {
+        Some(Ok(serverfn::PatientFiles {
+            patient,
+            files,
+            documents,
+        })) => {
+            // DocumentReferences of published files are shown as the files
+            let published = files
+                .iter()
+                .filter_map(|(_, f)| Some(f.metadata.fhir.as_ref()?.document_reference_id.as_str()))
+                .collect::<Vec<_>>();
+            let local_rows = files.iter().map(|(dir, file)| {
+                let on_server = file.metadata.fhir.as_ref().is_some_and(|fhir| {
+                    documents.iter().any(|d| d.id == fhir.document_reference_id)
+                });
+                let location = if dir.is_empty() {
+                    format!("/{}", file.name)
+                } else {
+                    format!("/{dir}/{}", file.name)
+                };
+                vec![
+                    file.name.clone(),
+                    if on_server { "Local and FHIR" } else { "Local" }.to_string(),
+                    file.kind.clone(),
+                    file.size.to_string(),
+                    file.modified.to_string(),
+                    location,
+                ]
+            });
+            let document_rows = documents
+                .iter()
+                .filter(|d| !published.contains(&d.id.as_str()))
+                .map(|d| {
+                    vec![
+                        d.title.clone(),
+                        "FHIR".to_string(),
+                        d.content_type.clone(),
+                        d.size.map(|size| size.to_string()).unwrap_or_default(),
+                        d.date.map(|date| date.to_string()).unwrap_or_default(),
+                        format!("DocumentReference/{}", d.id),
+                    ]
+                });
+            let identifiers = patient.identifiers.join(", ");
+            rsx! {
+                div { class: "m-4",
+                    h1 { class: "text-xl", "{patient.name}" }
+                    p { class: "text-sm text-gray-500",
+                        "Patient/{patient.id}"
+                        if !patient.identifiers.is_empty() {
+                            " · {identifiers}"
+                        }
+                        if !patient.birth_date.is_empty() {
+                            " · born {patient.birth_date}"
+                        }
+                        if !patient.gender.is_empty() {
+                            " · {patient.gender}"
+                        }
+                    }
+                }
+                table::Table {
+                    id: "patient-files",
+                    columns: vec![
+                        table::Column::new("Name"),
+                        table::Column::new("Source").categorical(),
+                        table::Column::new("Type").categorical(),
+                        table::Column::new("Size").bytes(),
+                        table::Column::new("Date").date(),
+                        table::Column::new("Location"),
+                    ],
+                    data: local_rows.chain(document_rows).collect::<Vec<_>>(),
+                    ondetail: |_| {},
+                    row_actions: vec![
+                        // Local files come before the DocumentReferences
+                        table::RowAction::new("Show in Parent Folder", {
+                            let files = files.clone();
+                            move |id: usize| {
+                                navigator().push(directory_route(&files[id].0));
+                            }
+                        })
+                        .enabled({
+                            let count = files.len();
+                            move |id: usize| id < count
+                        }),
+                    ],
+                }
+            }
+        }
+        Some(Err(e)) => rsx! { "Error loading patient: {e:#}" },
+        None => rsx! { "Loading..." },
+    }
+}
+
+/// Refreshes of the duplicates without any more files hashed, after which refreshing is left to
+/// the user
+const STALLED_REFRESHES: u32 = 3;
+
+/// Groups of files with identical content below the root directory. Copies can be selected and
+/// deleted, as long as one file of each group is kept.
+#[component]
+fn Duplicates() -> Element {
+    let mut report = use_server_future(serverfn::find_duplicates)?;
+    let mut error = use_signal(|| None::<String>);
+    // Files pending in the last report and the number of refreshes since fewer were pending
+    let mut progress = use_signal(|| (usize::MAX, 0));
+    // Look again while files are still being hashed. Finding duplicates waits for the hashes itself.
+    // Stops when hashing stalls, as a stuck worker would otherwise be asked again forever.
+    use_effect(move || {
+        let pending = match &*report.read() {
+            Some(Ok(report)) if report.pending > 0 => report.pending,
+            _ => return,
+        };
+        let (last_pending, refreshes) = *progress.peek();
+        let refreshes = if pending < last_pending {
+            0
+        } else {
+            refreshes + 1
+        };
+        progress.set((pending, refreshes));
+        if refreshes < STALLED_REFRESHES {
+            report.restart();
+        }
+    });
+    match &*report.read_unchecked() {
+        Some(Ok(serverfn::DuplicateReport { groups, pending })) => {
+            let wasted = groups.iter().map(|group| group.wasted()).sum::<u64>();
+            // Group index and directory of each row
+            let rows = groups
+                .iter()
+                .enumerate()
+                .flat_map(|(index, group)| {
+                    group
+                        .files
+                        .iter()
+                        .map(move |(dir, file)| (index, dir, file))
+                })
+                .collect::<Vec<_>>();
+            let data = rows
+                .iter()
+                .map(|&(index, dir, file)| {
+                    let group = &groups[index];
+                    vec![
+                        file.name.clone(),
+                        if dir.is_empty() {
+                            "/".to_string()
+                        } else {
+                            format!("/{dir}")
+                        },
+                        group.sha256[..12].to_string(),
+                        group.size.to_string(),
+                        group.files.len().to_string(),
+                        group.wasted().to_string(),
+                        file.modified.to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let selected = rows
+                .iter()
+                .map(|&(index, dir, file)| (index, dir.clone(), file.name.clone()))
+                .collect::<Vec<_>>();
+            let copies = groups
+                .iter()
+                .map(|group| group.files.len())
+                .collect::<Vec<_>>();
+            rsx! {
+                div { class: "m-4",
+                    h1 { class: "text-xl", "Duplicates" }
+                    p { class: "text-sm text-gray-500",
+                        "{groups.len()} groups of identical files, {format_bytes(wasted)} wasted"
+                        if *pending > 0 {
+                            " · still hashing {pending} files "
+                            button {
+                                class: "underline hover:text-gray-700",
+                                onclick: move |_| {
+                                    progress.set((usize::MAX, 0));
+                                    report.restart();
+                                },
+                                "Refresh"
+                            }
+                        }
+                    }
+                    if let Some(error) = error() {
+                        p { class: "text-sm text-red-600", "{error}" }
+                    }
+                }
+                table::Table {
+                    id: "duplicates",
+                    columns: vec![
+                        table::Column::new("Name"),
+                        table::Column::new("Directory").categorical(),
+                        table::Column::new("Group")
+                            .categorical()
+                            .collation(table::Collation::Binary),
+                        table::Column::new("Size").bytes(),
+                        table::Column::new("Copies").numeric(),
+                        table::Column::new("Wasted Space").bytes(),
+                        table::Column::new("Modified").date(),
+                    ],
+                    data,
+                    ondetail: |_| {},
+                    row_actions: vec![
+                        table::RowAction::new("Open", {
+                            let selected = selected.clone();
+                            move |id: usize| {
+                                let (_, dir, name) = &selected[id];
+                                export::file_link(export::FileLink::Open, dir, name);
+                            }
+                        }),
+                        table::RowAction::new("Show in Parent Folder", {
+                            let selected = selected.clone();
+                            move |id: usize| {
+                                navigator().push(directory_route(&selected[id].1));
+                            }
+                        }),
+                    ],
+                    selection_actions: vec![
+                        table::SelectionAction::new("Delete", move |ids: Vec<usize>| {
+                            let files = ids.iter().map(|&id| selected[id].clone()).collect::<Vec<_>>();
+                            let copies = copies.clone();
+                            async move {
+                                let keeps_copy = copies.iter().enumerate().all(|(index, &count)| {
+                                    files.iter().filter(|(i, _, _)| *i == index).count() < count
+                                });
+                                if !keeps_copy {
+                                    error.set(Some("Keep at least one file of each group".to_string()));
+                                    return;
+                                }
+                                let confirm = document::eval(
+                                    "return confirm('Delete ' + await dioxus.recv() + ' files?');",
+                                );
+                                let _ = confirm.send(files.len());
+                                if !matches!(confirm.join::<bool>().await, Ok(true)) {
+                                    return;
+                                }
+                                error.set(None);
+                                let mut by_dir = std::collections::BTreeMap::<String, Vec<String>>::new();
+                                for (_, dir, name) in files {
+                                    by_dir.entry(dir).or_default().push(name);
+                                }
+                                for (dir, names) in by_dir {
+                                    if let Err(e) = serverfn::delete_files(dir, names).await {
+                                        error.set(Some(format!("Failed to delete files: {e:#}")));
+                                        break;
+                                    }
+                                }
+                                report.restart();
+                            }
+                        }),
+                    ],
+                }
+            }
+        }
+        Some(Err(e)) => rsx! { "Error finding duplicates: {e:#}" },
+        None => rsx! { "Loading..." },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_values_are_finite_numbers() {
+        let kind = serverfn::FieldKind::Number;
+        assert_eq!(
+            parse_field_value(kind, " 1.5 "),
+            Ok(Some(serverfn::FieldValue::Number(1.5)))
+        );
+        assert_eq!(parse_field_value(kind, ""), Ok(None));
+        for text in ["NaN", "inf", "-infinity", "1e400", "x"] {
+            assert!(parse_field_value(kind, text).is_err(), "{text}");
+            assert_eq!(
+                infer_field_value(text),
+                serverfn::FieldValue::Text(text.to_string())
+            );
+        }
+        assert_eq!(infer_field_value("-2"), serverfn::FieldValue::Number(-2.0));
+    }
+}
@@ -1,27 +1,602 @@
-// #![cfg(feature = "server")]
-
-// use std::collections::HashMap;
-
-// #[derive(Debug, serde::Deserialize)]
-// #[serde(deny_unknown_fields)]
-// pub struct Config {
-//     pub fhir_base_url: String,
-//     pub fhir_username: Option<String>,
-//     pub fhir_password: Option<String>,
-//     #[serde(default)]
-//     pub accept_invalid_certs: bool,
-// }
-
-// static CONFIG: std::sync::OnceLock<Config> = std::sync::OnceLock::new();
-
-// /// Load the configuration from scout.toml. Should be called once on server startup.
-// pub fn load_config() -> anyhow::Result<()> {
-//     let config_str = std::fs::read_to_string("scout.toml")?;
-//     let config = toml::from_str(&config_str)?;
-//     CONFIG.set(config).expect("Config should only be set once");
-//     Ok(())
-// }
-
-// pub fn config() -> &'static Config {
-//     CONFIG.get().expect("Config should be loaded before use")
-// }
+#![cfg(feature = "server")]
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// FHIR integration is disabled if not set
+    pub fhir_base_url: Option<String>,
+    pub fhir_username: Option<String>,
+    pub fhir_password: Option<String>,
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    /// Compute MD5 checksums besides SHA-256, for legacy tools
+    #[serde(default)]
+    pub md5_checksums: bool,
+}
+
+const CONFIG_PATH: &str = "scout.toml";
+
+static CONFIG: std::sync::OnceLock<Config> = std::sync::OnceLock::new();
+
+/// Load the configuration from scout.toml. Should be called once on server startup. Without the
+/// file, the defaults are used and the app runs without FHIR integration.
+pub fn load_config() -> anyhow::Result<()> {
+    let config = match std::fs::read_to_string(CONFIG_PATH) {
+        Ok(config_str) => toml::from_str(&config_str)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::warn!("{CONFIG_PATH} not found, FHIR integration is disabled");
+            Config::default()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    CONFIG.set(config).expect("Config should only be set once");
+    Ok(())
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get().expect("Config should be loaded before use")
+}
+
+const VIEWS_PATH: &str = "views.json";
+
+/// Serializes read-modify-write cycles on the views file
+static VIEWS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Load the saved table views. Returns an empty list if none have been saved yet.
+pub fn load_views() -> anyhow::Result<Vec<crate::table::View>> {
+    match std::fs::read_to_string(VIEWS_PATH) {
+        Ok(views_str) => Ok(serde_json::from_str(&views_str)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Modify the saved table views and write them back to disk
+pub fn update_views(f: impl FnOnce(&mut Vec<crate::table::View>)) -> anyhow::Result<()> {
+    let _guard = VIEWS_LOCK.lock().unwrap();
+    let mut views = load_views()?;
+    f(&mut views);
+    std::fs::write(VIEWS_PATH, serde_json::to_string_pretty(&views)?)?;
+    Ok(())
+}
+
+const METADATA_PATH: &str = "metadata.db";
+
+/// Where earlier versions kept the metadata, imported when the database is created
+const LEGACY_METADATA_PATH: &str = "metadata.json";
+
+/// Connection to the metadata database, opened on first use. Holding the lock serializes access,
+/// so read-modify-write cycles don't interleave.
+static METADATA_DB: std::sync::Mutex<Option<rusqlite::Connection>> = std::sync::Mutex::new(None);
+
+fn with_metadata_db<T>(
+    f: impl FnOnce(&mut rusqlite::Connection) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let mut db = METADATA_DB.lock().unwrap();
+    if db.is_none() {
+        let mut connection = rusqlite::Connection::open(METADATA_PATH)?;
+        migrate_metadata(&mut connection, std::path::Path::new(LEGACY_METADATA_PATH))?;
+        *db = Some(connection);
+    }
+    f(db.as_mut().unwrap())
+}
+
+/// Create the metadata table in a new database, importing the metadata file of earlier versions
+fn migrate_metadata(
+    connection: &mut rusqlite::Connection,
+    legacy: &std::path::Path,
+) -> anyhow::Result<()> {
+    let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > 0 {
+        return Ok(());
+    }
+    let transaction = connection.transaction()?;
+    // Metadata is stored as JSON by path relative to the root directory
+    transaction.execute_batch(
+        "CREATE TABLE metadata (path TEXT PRIMARY KEY, metadata TEXT NOT NULL) WITHOUT ROWID;
+        PRAGMA user_version = 1;",
+    )?;
+    match std::fs::read_to_string(legacy) {
+        Ok(metadata_str) => {
+            let metadata: BTreeMap<String, crate::serverfn::FileMetadata> =
+                serde_json::from_str(&metadata_str)?;
+            for (key, metadata) in &metadata {
+                put_metadata(&transaction, key, metadata)?;
+            }
+            tracing::info!("Imported {} into the metadata database", legacy.display());
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn get_metadata(
+    connection: &rusqlite::Connection,
+    key: &str,
+) -> anyhow::Result<crate::serverfn::FileMetadata> {
+    use rusqlite::OptionalExtension;
+    let metadata_str = connection
+        .query_row(
+            "SELECT metadata FROM metadata WHERE path = ?1",
+            [key],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    Ok(match metadata_str {
+        Some(metadata_str) => serde_json::from_str(&metadata_str)?,
+        None => Default::default(),
+    })
+}
+
+/// Store the metadata of a file, removing its entry if the metadata is empty
+fn put_metadata(
+    connection: &rusqlite::Connection,
+    key: &str,
+    metadata: &crate::serverfn::FileMetadata,
+) -> anyhow::Result<()> {
+    if *metadata == Default::default() {
+        connection.execute("DELETE FROM metadata WHERE path = ?1", [key])?;
+    } else {
+        connection.execute(
+            "INSERT INTO metadata (path, metadata) VALUES (?1, ?2)
+            ON CONFLICT (path) DO UPDATE SET metadata = excluded.metadata",
+            (key, serde_json::to_string(metadata)?),
+        )?;
+    }
+    Ok(())
+}
+
+fn all_metadata(
+    connection: &rusqlite::Connection,
+) -> anyhow::Result<BTreeMap<String, crate::serverfn::FileMetadata>> {
+    let mut statement = connection.prepare("SELECT path, metadata FROM metadata")?;
+    let rows = statement.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut metadata = BTreeMap::new();
+    for row in rows {
+        let (key, metadata_str) = row?;
+        metadata.insert(key, serde_json::from_str(&metadata_str)?);
+    }
+    Ok(metadata)
+}
+
+fn update_metadata_in(
+    connection: &mut rusqlite::Connection,
+    key: &str,
+    f: impl FnOnce(&mut crate::serverfn::FileMetadata),
+) -> anyhow::Result<()> {
+    update_metadata_batch_in(connection, vec![(key.to_string(), f)], |metadata, f| {
+        f(metadata)
+    })
+}
+
+fn update_metadata_batch_in<T>(
+    connection: &mut rusqlite::Connection,
+    updates: Vec<(String, T)>,
+    mut f: impl FnMut(&mut crate::serverfn::FileMetadata, T),
+) -> anyhow::Result<()> {
+    let transaction = connection.transaction()?;
+    for (key, update) in updates {
+        let mut metadata = get_metadata(&transaction, &key)?;
+        f(&mut metadata, update);
+        put_metadata(&transaction, &key, &metadata)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Move metadata to a new path. Should run in a transaction, as it takes more than one statement.
+fn move_metadata_in(connection: &rusqlite::Connection, from: &str, to: &str) -> anyhow::Result<()> {
+    // The path itself and everything below it
+    const BELOW: &str = "path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'";
+    // Left behind by files that were removed without Scout knowing
+    connection.execute(&format!("DELETE FROM metadata WHERE {BELOW}"), [to])?;
+    connection.execute(
+        &format!("UPDATE metadata SET path = ?2 || substr(path, length(?1) + 1) WHERE {BELOW}"),
+        [from, to],
+    )?;
+    Ok(())
+}
+
+fn move_files_in(
+    connection: &mut rusqlite::Connection,
+    root: &std::path::Path,
+    names: &[String],
+    from: &str,
+    to: &str,
+) -> anyhow::Result<()> {
+    let from_dir = resolve_path_in(root, from)?;
+    let to_dir = resolve_path_in(root, to)?;
+    let (from, to) = (join_path(from, ""), join_path(to, ""));
+    if from == to {
+        anyhow::bail!("The files are already in this directory");
+    }
+    for name in names {
+        check_file_name(name)?;
+        let moved = join_path(&from, name);
+        if to == moved || to.starts_with(&format!("{moved}/")) {
+            anyhow::bail!("Can't move {name} into itself");
+        }
+        if to_dir.join(name).exists() {
+            anyhow::bail!("{name} already exists");
+        }
+    }
+    let transaction = connection.transaction()?;
+    let mut moved = Vec::new();
+    let result = names.iter().try_for_each(|name| {
+        std::fs::rename(from_dir.join(name), to_dir.join(name))
+            .map_err(|e| anyhow::anyhow!("Failed to move {name}: {e}"))?;
+        moved.push(name);
+        move_metadata_in(&transaction, &join_path(&from, name), &join_path(&to, name))
+    });
+    if let Err(e) = result.and_then(|()| Ok(transaction.commit()?)) {
+        // The metadata was rolled back with the transaction, so put the files back to match it
+        for name in moved.into_iter().rev() {
+            if let Err(e) = std::fs::rename(to_dir.join(name), from_dir.join(name)) {
+                tracing::error!("Failed to move {name} back to {from:?}: {e}");
+            }
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Load what Scout records about files, by path relative to the root directory. Files without an
+/// entry have no metadata.
+pub fn load_metadata() -> anyhow::Result<BTreeMap<String, crate::serverfn::FileMetadata>> {
+    with_metadata_db(|connection| all_metadata(connection))
+}
+
+/// Modify the metadata of a file, given as a path relative to the root directory. Metadata left
+/// empty is removed.
+pub fn update_metadata(
+    key: &str,
+    f: impl FnOnce(&mut crate::serverfn::FileMetadata),
+) -> anyhow::Result<()> {
+    with_metadata_db(|connection| update_metadata_in(connection, key, f))
+}
+
+/// Modify the metadata of several files in one transaction, applying `f` to the metadata of each
+/// file with the update given for it
+pub fn update_metadata_batch<T>(
+    updates: Vec<(String, T)>,
+    f: impl FnMut(&mut crate::serverfn::FileMetadata, T),
+) -> anyhow::Result<()> {
+    with_metadata_db(|connection| update_metadata_batch_in(connection, updates, f))
+}
+
+/// Move the metadata of a file or directory and everything below it to a new path
+pub fn move_metadata(from: &str, to: &str) -> anyhow::Result<()> {
+    with_metadata_db(|connection| {
+        let transaction = connection.transaction()?;
+        move_metadata_in(&transaction, from, to)?;
+        transaction.commit()?;
+        Ok(())
+    })
+}
+
+/// Move files and directories from one directory to another along with their metadata, given as
+/// paths relative to the root directory. Nothing is moved if a name is taken or a directory would
+/// be moved into itself. If a move fails, the files moved before it are moved back.
+pub fn move_files(names: &[String], from: &str, to: &str) -> anyhow::Result<()> {
+    with_metadata_db(|connection| {
+        move_files_in(connection, std::path::Path::new(ROOT_DIR), names, from, to)
+    })
+}
+
+/// Path of a file relative to the root directory, as used for metadata
+pub fn join_path(dir: &str, name: &str) -> String {
+    itertools::Itertools::join(
+        &mut dir.split('/').filter(|c| !c.is_empty()).chain([name]),
+        "/",
+    )
+}
+
+/// Directory whose contents are listed
+const ROOT_DIR: &str = "testfiles";
+
+/// A file or directory as listed in tables, without its metadata. Fields not yet extracted
+/// are read in the background, checksums are left to `crate::checksum::queue_if_stale`.
+pub fn file_info(path: &std::path::Path) -> Option<crate::serverfn::File> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let fields = crate::extract::cached_fields(path, &metadata);
+    let fields_pending = fields.is_none();
+    let kind = if metadata.is_dir() {
+        "Directory".to_string()
+    } else {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_uppercase())
+            .unwrap_or_else(|| "File".to_string())
+    };
+    Some(crate::serverfn::File {
+        name: path.file_name()?.to_string_lossy().to_string(),
+        kind,
+        size: metadata.len(),
+        modified: metadata.modified().ok()?.try_into().ok()?,
+        metadata: Default::default(),
+        fields: fields.unwrap_or_default(),
+        fields_pending,
+        checksum_pending: false,
+    })
+}
+
+/// All files below the root directory with their path relative to it and their size. Symbolic
+/// links are not followed.
+pub fn walk_files() -> anyhow::Result<Vec<(String, std::path::PathBuf, u64)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![(String::new(), std::path::PathBuf::from(ROOT_DIR))];
+    while let Some((dir, dir_path)) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push((join_path(&dir, &name), entry.path()));
+            } else if file_type.is_file() {
+                files.push((
+                    join_path(&dir, &name),
+                    entry.path(),
+                    entry.metadata()?.len(),
+                ));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// MIME type of a file guessed from its extension
+pub fn mime_type(name: &str) -> &'static str {
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("csv") => "text/csv",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("html" | "htm") => "text/html",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("dcm") => "application/dicom",
+        Some("vcf") => "text/vcf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Check that a file name refers to an entry directly inside a directory
+pub fn check_file_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        anyhow::bail!("Invalid file name: {name:?}");
+    }
+    Ok(())
+}
+
+/// Wait while work is pending, checking again whenever the counter of finished work changes, or
+/// until the timeout elapses
+pub async fn wait_while(
+    finished: &tokio::sync::watch::Sender<u64>,
+    is_pending: impl Fn() -> bool,
+    timeout: std::time::Duration,
+) {
+    let mut finished = finished.subscribe();
+    let _ = tokio::time::timeout(timeout, async {
+        while is_pending() {
+            if finished.changed().await.is_err() {
+                return;
+            }
+        }
+    })
+    .await;
+}
+
+/// Resolve a slash-separated path relative to the root directory, rejecting paths that leave it
+pub fn resolve_path(path: &str) -> anyhow::Result<std::path::PathBuf> {
+    resolve_path_in(std::path::Path::new(ROOT_DIR), path)
+}
+
+/// Resolve a path relative to a directory. Besides `..`, symbolic links to outside the directory
+/// are rejected. The path needn't exist, so only the part that does is checked for links.
+fn resolve_path_in(root: &std::path::Path, path: &str) -> anyhow::Result<std::path::PathBuf> {
+    let mut resolved = root.to_path_buf();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        check_file_name(component)?;
+        resolved.push(component);
+    }
+    let existing = resolved
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(root);
+    if !existing.canonicalize()?.starts_with(root.canonicalize()?) {
+        anyhow::bail!("{path} is outside the root directory");
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> rusqlite::Connection {
+        let mut connection = rusqlite::Connection::open_in_memory().unwrap();
+        migrate_metadata(&mut connection, std::path::Path::new("missing.json")).unwrap();
+        connection
+    }
+
+    fn tag(connection: &mut rusqlite::Connection, key: &str, tag: &str) {
+        update_metadata_in(connection, key, |metadata| {
+            metadata.tags.insert(tag.to_string());
+        })
+        .unwrap();
+    }
+
+    fn tags(connection: &rusqlite::Connection) -> Vec<(String, Vec<String>)> {
+        all_metadata(connection)
+            .unwrap()
+            .into_iter()
+            .map(|(key, metadata)| (key, metadata.tags.into_iter().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn update_and_remove_empty() {
+        let mut connection = database();
+        tag(&mut connection, "a/b.txt", "raw");
+        tag(&mut connection, "a/b.txt", "qc");
+        update_metadata_in(&mut connection, "a/b.txt", |metadata| {
+            metadata.notes = "Rerun".to_string()
+        })
+        .unwrap();
+        let metadata = get_metadata(&connection, "a/b.txt").unwrap();
+        assert_eq!(metadata.tags.iter().collect::<Vec<_>>(), ["qc", "raw"]);
+        assert_eq!(metadata.notes, "Rerun");
+
+        update_metadata_in(&mut connection, "a/b.txt", |metadata| {
+            *metadata = Default::default()
+        })
+        .unwrap();
+        assert!(all_metadata(&connection).unwrap().is_empty());
+        assert_eq!(
+            get_metadata(&connection, "a/b.txt").unwrap(),
+            Default::default()
+        );
+    }
+
+    #[test]
+    fn move_with_descendants() {
+        let mut connection = database();
+        for key in [
+            "run",
+            "run/1.fq",
+            "run/sub/2.fq",
+            "run2/3.fq",
+            "other/run/4.fq",
+            "new/old.fq",
+        ] {
+            tag(&mut connection, key, key);
+        }
+        move_metadata_in(&connection, "run", "new").unwrap();
+        assert_eq!(
+            tags(&connection),
+            [
+                ("new".to_string(), vec!["run".to_string()]),
+                ("new/1.fq".to_string(), vec!["run/1.fq".to_string()]),
+                ("new/sub/2.fq".to_string(), vec!["run/sub/2.fq".to_string()]),
+                (
+                    "other/run/4.fq".to_string(),
+                    vec!["other/run/4.fq".to_string()]
+                ),
+                ("run2/3.fq".to_string(), vec!["run2/3.fq".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_legacy_file() {
+        let legacy =
+            std::env::temp_dir().join(format!("scout-{}-metadata.json", std::process::id()));
+        std::fs::write(
+            &legacy,
+            r#"{"a/b.txt": {"tags": ["raw"], "notes": "Rerun"}}"#,
+        )
+        .unwrap();
+        let mut connection = rusqlite::Connection::open_in_memory().unwrap();
+        migrate_metadata(&mut connection, &legacy).unwrap();
+        std::fs::remove_file(&legacy).unwrap();
+        assert_eq!(
+            tags(&connection),
+            [("a/b.txt".to_string(), vec!["raw".to_string()])]
+        );
+        assert_eq!(get_metadata(&connection, "a/b.txt").unwrap().notes, "Rerun");
+        // Only imported when the database is created
+        migrate_metadata(&mut connection, std::path::Path::new("missing.json")).unwrap();
+        assert_eq!(all_metadata(&connection).unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_links_out_of_root() {
+        let temp = std::env::temp_dir().join(format!("scout-{}-resolve", std::process::id()));
+        let root = temp.join("root");
+        let outside = temp.join("outside");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("sub/secret.txt"))
+            .unwrap();
+        std::os::unix::fs::symlink(root.join("sub"), root.join("inside")).unwrap();
+
+        let resolved = |path: &str| resolve_path_in(&root, path).map_err(|e| e.to_string());
+        assert_eq!(resolved("sub"), Ok(root.join("sub")));
+        assert_eq!(resolved("sub/new.txt"), Ok(root.join("sub/new.txt")));
+        assert_eq!(resolved("inside/new.txt"), Ok(root.join("inside/new.txt")));
+        assert_eq!(resolved(""), Ok(root.clone()));
+        for path in [
+            "link",
+            "link/secret.txt",
+            "link/new/new.txt",
+            "sub/secret.txt",
+        ] {
+            assert_eq!(
+                resolved(path),
+                Err(format!("{path} is outside the root directory"))
+            );
+        }
+        assert!(resolved("sub/../../outside").is_err());
+        std::fs::remove_dir_all(&temp).unwrap();
+    }
+
+    #[test]
+    fn move_files_checks_before_moving() {
+        let root = std::env::temp_dir().join(format!("scout-{}-move", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("run/sub")).unwrap();
+        std::fs::create_dir_all(root.join("other")).unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        std::fs::write(root.join("other/a.txt"), "other").unwrap();
+        let mut connection = database();
+        tag(&mut connection, "run/sub", "sub");
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let move_files = |connection: &mut rusqlite::Connection, names: &[String], from, to| {
+            move_files_in(connection, &root, names, from, to).map_err(|e| e.to_string())
+        };
+
+        assert_eq!(
+            move_files(&mut connection, &names(&["run"]), "", "run/sub"),
+            Err("Can't move run into itself".to_string())
+        );
+        assert_eq!(
+            move_files(&mut connection, &names(&["run"]), "/", "run"),
+            Err("Can't move run into itself".to_string())
+        );
+        assert_eq!(
+            move_files(&mut connection, &names(&["a.txt"]), "other", "other/"),
+            Err("The files are already in this directory".to_string())
+        );
+        assert_eq!(
+            move_files(&mut connection, &names(&["run", "a.txt"]), "", "other"),
+            Err("a.txt already exists".to_string())
+        );
+        assert!(root.join("run/sub").is_dir());
+
+        // Moves "run" into "run2", then fails at the missing file and moves "run" back
+        std::fs::create_dir(root.join("run2")).unwrap();
+        let error =
+            move_files(&mut connection, &names(&["run", "missing.txt"]), "", "run2").unwrap_err();
+        assert!(error.starts_with("Failed to move missing.txt"), "{error}");
+        assert!(root.join("run/sub").is_dir());
+        assert!(!root.join("run2/run").exists());
+        assert_eq!(tags(&connection)[0].0, "run/sub");
+
+        move_files(&mut connection, &names(&["run", "a.txt"]), "", "run2").unwrap();
+        assert!(root.join("run2/run/sub").is_dir());
+        assert!(root.join("run2/a.txt").is_file());
+        assert_eq!(tags(&connection)[0].0, "run2/run/sub");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,19 +1,546 @@
-use crate::server;
+use crate::table::View;
 use dioxus::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct File {
     pub name: String,
+    /// "Directory" for directories, otherwise the upper-cased file extension
+    pub kind: String,
+    pub size: u64,
+    pub modified: jiff::Timestamp,
+    pub metadata: FileMetadata,
+    /// Fields read from the contents by metadata extractors, by field name
+    pub fields: Vec<(String, FieldValue)>,
+    /// Whether the fields are still being read and the file should be listed again later
+    pub fields_pending: bool,
+    /// Whether the file is waiting to be hashed and should be listed again later
+    pub checksum_pending: bool,
 }
 
+/// Checksums of the content of a file as lowercase hex, as of its size and modification time
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Checksum {
+    pub sha256: String,
+    /// Only computed if enabled in scout.toml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    pub size: u64,
+    pub modified: jiff::Timestamp,
+    /// When the content was last hashed again and found unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<jiff::Timestamp>,
+    /// SHA-256 found when hashing again, if the content changed although the size and
+    /// modification time didn't
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mismatch: Option<String>,
+}
+
+/// Files with identical content
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    pub sha256: String,
+    pub size: u64,
+    /// The files with the directory they are in
+    pub files: Vec<(String, File)>,
+}
+
+impl DuplicateGroup {
+    /// Space taken by all copies but one
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct DuplicateReport {
+    /// Groups of two or more files, most wasted space first
+    pub groups: Vec<DuplicateGroup>,
+    /// Files that may be duplicates but haven't been hashed yet
+    pub pending: usize,
+}
+
+/// What Scout records about a file beyond what the file system knows
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default, Debug)]
+pub struct FileMetadata {
+    /// Set once the file has been published to the FHIR server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fhir: Option<FhirPublication>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Fields set by users, by field name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_fields: BTreeMap<String, FieldValue>,
+}
+
+/// A field read from the contents of a file
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+    Date(jiff::civil::Date),
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Text(text) => text.fmt(f),
+            FieldValue::Number(number) => number.fmt(f),
+            FieldValue::Date(date) => date.fmt(f),
+        }
+    }
+}
+
+/// How the values of a field are shown in a table column
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum FieldKind {
+    Text,
+    /// Text with few distinct values, filtered by category
+    Category,
+    Number,
+    Date,
+}
+
+/// A field of files, read by metadata extractors or set by users
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FieldInfo {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+#[cfg(feature = "server")]
+impl FieldInfo {
+    pub fn new(name: &str, kind: FieldKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+        }
+    }
+}
+
+/// Ids of the resources created on the FHIR server when publishing a file
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FhirPublication {
+    pub patient_id: String,
+    pub binary_id: String,
+    pub document_reference_id: String,
+    pub published: jiff::Timestamp,
+}
+
+/// The fields of a FHIR DocumentReference shown in tables
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct DocumentReference {
+    pub id: String,
+    /// Title of the attachment, or the description of the DocumentReference if it has none
+    pub title: String,
+    /// Display name of the subject, or the reference if there is none
+    pub subject: String,
+    pub kind: String,
+    pub date: Option<jiff::Timestamp>,
+    pub content_type: String,
+    pub size: Option<u64>,
+}
+
+/// The fields of a FHIR Patient shown in tables
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Patient {
+    pub id: String,
+    pub name: String,
+    pub identifiers: Vec<String>,
+    /// Date of birth as given by the server, which may be just a year
+    pub birth_date: String,
+    pub gender: String,
+}
+
+/// A Patient with everything linked to it
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct PatientFiles {
+    pub patient: Patient,
+    /// Local files published for the patient, with their paths relative to the root directory
+    pub files: Vec<(String, File)>,
+    /// DocumentReferences on the FHIR server with the patient as subject
+    pub documents: Vec<DocumentReference>,
+}
+
+/// List the files in a directory, given as a path relative to the root directory
 #[server]
-pub async fn get_files() -> Result<Vec<File>, ServerFnError> {
-    Ok(std::fs::read_dir("testfiles")?
+pub async fn get_files(path: String) -> Result<Vec<File>, ServerFnError> {
+    let dir = crate::server::resolve_path(&path).map_err(ServerFnError::new)?;
+    let mut metadata = crate::server::load_metadata().map_err(ServerFnError::new)?;
+    Ok(std::fs::read_dir(dir)?
         .filter_map(|res| res.ok())
-        .map(|entry| File {
-            name: entry.file_name().to_string_lossy().to_string(),
+        .filter_map(|entry| {
+            let mut file = crate::server::file_info(&entry.path())?;
+            let key = crate::server::join_path(&path, &file.name);
+            file.metadata = metadata.remove(&key).unwrap_or_default();
+            file.checksum_pending = crate::checksum::queue_if_stale(&entry.path(), &key, &file);
+            Some(file)
         })
         .collect())
 }
+
+/// Rename a file in a directory
+#[server]
+pub async fn rename_file(
+    path: String,
+    name: String,
+    new_name: String,
+) -> Result<(), ServerFnError> {
+    crate::server::check_file_name(&name).map_err(ServerFnError::new)?;
+    crate::server::check_file_name(&new_name).map_err(ServerFnError::new)?;
+    let dir = crate::server::resolve_path(&path).map_err(ServerFnError::new)?;
+    if dir.join(&new_name).exists() {
+        return Err(ServerFnError::new(format!("{new_name} already exists")));
+    }
+    std::fs::rename(dir.join(&name), dir.join(&new_name))?;
+    crate::server::move_metadata(
+        &crate::server::join_path(&path, &name),
+        &crate::server::join_path(&path, &new_name),
+    )
+    .map_err(ServerFnError::new)
+}
+
+/// Move files from one directory to another. Nothing is moved if a name is taken or a directory
+/// would be moved into itself, and nothing stays moved if a move fails.
+#[server]
+pub async fn move_files(names: Vec<String>, from: String, to: String) -> Result<(), ServerFnError> {
+    crate::server::move_files(&names, &from, &to).map_err(ServerFnError::new)
+}
+
+/// List the DocumentReferences on the configured FHIR server
+#[server]
+pub async fn get_document_references() -> Result<Vec<DocumentReference>, ServerFnError> {
+    let client = crate::fhir::Client::from_config().map_err(ServerFnError::new)?;
+    client
+        .document_references(&[])
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// Search Patients on the FHIR server by identifier and name
+#[server]
+pub async fn search_patients(query: String) -> Result<Vec<Patient>, ServerFnError> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let client = crate::fhir::Client::from_config().map_err(ServerFnError::new)?;
+    client
+        .search_patients(query.trim())
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// Resolve a Patient on the FHIR server and find the local files and DocumentReferences linked to it
+#[server]
+pub async fn get_patient_files(id: String) -> Result<PatientFiles, ServerFnError> {
+    // The id comes from the route, and is also used in the subject search parameter
+    crate::fhir::check_id(&id).map_err(ServerFnError::new)?;
+    let client = crate::fhir::Client::from_config().map_err(ServerFnError::new)?;
+    let patient = client.patient(&id).await.map_err(ServerFnError::new)?;
+    let documents = client
+        .document_references(&[("subject", &format!("Patient/{id}"))])
+        .await
+        .map_err(ServerFnError::new)?;
+    let files = crate::server::load_metadata()
+        .map_err(ServerFnError::new)?
+        .into_iter()
+        .filter(|(_, m)| m.fhir.as_ref().is_some_and(|fhir| fhir.patient_id == id))
+        // Files deleted since they were published are left out
+        .filter_map(|(path, metadata)| {
+            let mut file = crate::server::file_info(&crate::server::resolve_path(&path).ok()?)?;
+            file.metadata = metadata;
+            let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            Some((dir.to_string(), file))
+        })
+        .collect();
+    Ok(PatientFiles {
+        patient,
+        files,
+        documents,
+    })
+}
+
+/// Publish files in a directory to the FHIR server as Binary resources with DocumentReferences
+/// pointing at them for a Patient, and record the ids. Stops at the first file that fails, keeping
+/// the files published before it.
+#[server]
+pub async fn publish_files(
+    path: String,
+    names: Vec<String>,
+    patient_id: String,
+) -> Result<(), ServerFnError> {
+    let client = crate::fhir::Client::from_config().map_err(ServerFnError::new)?;
+    let dir = crate::server::resolve_path(&path).map_err(ServerFnError::new)?;
+    // Fail early with a clear message if the patient doesn't exist
+    client
+        .read("Patient", &patient_id)
+        .await
+        .map_err(ServerFnError::new)?;
+    for name in &names {
+        crate::server::check_file_name(name).map_err(ServerFnError::new)?;
+        let content = std::fs::read(dir.join(name))?;
+        let publication = client
+            .publish(name, content, &patient_id)
+            .await
+            .map_err(|e| ServerFnError::new(format!("Failed to publish {name}: {e:#}")))?;
+        crate::server::update_metadata(&crate::server::join_path(&path, name), |metadata| {
+            metadata.fhir = Some(publication);
+        })
+        .map_err(ServerFnError::new)?;
+    }
+    Ok(())
+}
+
+/// The fields metadata extractors can return
+#[server]
+pub async fn get_metadata_fields() -> Result<Vec<FieldInfo>, ServerFnError> {
+    Ok(crate::extract::fields())
+}
+
+/// Wait until the files in a directory have been read for fields and hashed, for at most 30 seconds
+#[server]
+pub async fn wait_for_metadata(path: String) -> Result<(), ServerFnError> {
+    let dir = crate::server::resolve_path(&path).map_err(ServerFnError::new)?;
+    let timeout = std::time::Duration::from_secs(30);
+    let deadline = std::time::Instant::now() + timeout;
+    crate::extract::wait_for_directory(&dir, timeout).await;
+    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+    crate::checksum::wait_for_directory(&dir, remaining).await;
+    Ok(())
+}
+
+/// Find files below the root directory with identical content. Only files sharing their size with
+/// another file are hashed, waiting at most 30 seconds for the hashes.
+#[server]
+pub async fn find_duplicates() -> Result<DuplicateReport, ServerFnError> {
+    let mut by_size = BTreeMap::<u64, Vec<(String, std::path::PathBuf)>>::new();
+    for (key, path, size) in crate::server::walk_files().map_err(ServerFnError::new)? {
+        if size > 0 {
+            by_size.entry(size).or_default().push((key, path));
+        }
+    }
+    let candidates = by_size
+        .into_values()
+        .filter(|files| files.len() > 1)
+        .flatten()
+        .collect::<Vec<_>>();
+    let list = || -> anyhow::Result<Vec<(String, File)>> {
+        let mut metadata = crate::server::load_metadata()?;
+        Ok(candidates
+            .iter()
+            .filter_map(|(key, path)| {
+                let mut file = crate::server::file_info(path)?;
+                file.metadata = metadata.remove(key).unwrap_or_default();
+                file.checksum_pending = crate::checksum::queue_if_stale(path, key, &file);
+                let dir = key.rsplit_once('/').map_or("", |(dir, _)| dir);
+                Some((dir.to_string(), file))
+            })
+            .collect())
+    };
+    let mut files = list().map_err(ServerFnError::new)?;
+    if files.iter().any(|(_, file)| file.checksum_pending) {
+        let paths = candidates
+            .iter()
+            .map(|(_, path)| path.clone())
+            .collect::<Vec<_>>();
+        crate::checksum::wait_for_files(&paths, std::time::Duration::from_secs(30)).await;
+        files = list().map_err(ServerFnError::new)?;
+    }
+    let pending = files
+        .iter()
+        .filter(|(_, file)| file.checksum_pending)
+        .count();
+    let mut groups = BTreeMap::<(String, u64), Vec<(String, File)>>::new();
+    for (dir, file) in files {
+        if let Some(checksum) = file
+            .metadata
+            .checksum
+            .as_ref()
+            .filter(|_| !file.checksum_pending)
+        {
+            // The current content of files that changed since they were first hashed
+            let sha256 = checksum
+                .mismatch
+                .as_ref()
+                .unwrap_or(&checksum.sha256)
+                .clone();
+            groups
+                .entry((sha256, file.size))
+                .or_default()
+                .push((dir, file));
+        }
+    }
+    let mut groups = groups
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|((sha256, size), files)| DuplicateGroup {
+            sha256,
+            size,
+            files,
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.wasted()));
+    Ok(DuplicateReport { groups, pending })
+}
+
+/// Delete files in a directory along with their metadata. Directories are not deleted.
+#[server]
+pub async fn delete_files(path: String, names: Vec<String>) -> Result<(), ServerFnError> {
+    let dir = crate::server::resolve_path(&path).map_err(ServerFnError::new)?;
+    for name in &names {
+        crate::server::check_file_name(name).map_err(ServerFnError::new)?;
+        std::fs::remove_file(dir.join(name))?;
+        crate::server::update_metadata(&crate::server::join_path(&path, name), |metadata| {
+            *metadata = Default::default();
+        })
+        .map_err(ServerFnError::new)?;
+    }
+    Ok(())
+}
+
+/// Hash files in a directory again to check that their content matches their checksums
+#[server]
+pub async fn verify_checksums(path: String, names: Vec<String>) -> Result<(), ServerFnError> {
+    let dir = crate::server::resolve_path(&path).map_err(ServerFnError::new)?;
+    for name in names {
+        crate::server::check_file_name(&name).map_err(ServerFnError::new)?;
+        crate::checksum::verify(&dir.join(&name), &crate::server::join_path(&path, &name));
+    }
+    Ok(())
+}
+
+/// Replace the tags of a file in a directory
+#[server]
+pub async fn set_tags(
+    path: String,
+    name: String,
+    tags: BTreeSet<String>,
+) -> Result<(), ServerFnError> {
+    update_file_metadata(&path, &name, |metadata| metadata.tags = tags)
+}
+
+/// Add a tag to files in a directory
+#[server]
+pub async fn add_tag(path: String, names: Vec<String>, tag: String) -> Result<(), ServerFnError> {
+    for name in names {
+        update_file_metadata(&path, &name, |metadata| {
+            metadata.tags.insert(tag.clone());
+        })?;
+    }
+    Ok(())
+}
+
+#[server]
+pub async fn set_notes(path: String, name: String, notes: String) -> Result<(), ServerFnError> {
+    update_file_metadata(&path, &name, |metadata| metadata.notes = notes)
+}
+
+/// Set a custom field of a file in a directory, or remove it if the value is `None`
+#[server]
+pub async fn set_custom_field(
+    path: String,
+    name: String,
+    field: String,
+    value: Option<FieldValue>,
+) -> Result<(), ServerFnError> {
+    let field = field.trim().to_string();
+    if field.is_empty() {
+        return Err(ServerFnError::new("Field name must not be empty"));
+    }
+    if RESERVED_FIELD_NAMES.contains(&field.as_str())
+        || crate::extract::fields().iter().any(|f| f.name == field)
+    {
+        return Err(ServerFnError::new(format!("{field} is already a column")));
+    }
+    if let Some(FieldValue::Number(number)) = value
+        && !number.is_finite()
+    {
+        return Err(ServerFnError::new("Numbers must be finite"));
+    }
+    update_file_metadata(&path, &name, |metadata| match value {
+        Some(value) => {
+            metadata.custom_fields.insert(field, value);
+        }
+        None => {
+            metadata.custom_fields.remove(&field);
+        }
+    })
+}
+
+/// The custom fields set on any file. The kind of a field is taken from its first value.
+#[server]
+pub async fn get_custom_fields() -> Result<Vec<FieldInfo>, ServerFnError> {
+    let metadata = crate::server::load_metadata().map_err(ServerFnError::new)?;
+    let mut fields = BTreeMap::new();
+    for (name, value) in metadata.values().flat_map(|m| &m.custom_fields) {
+        fields.entry(name.clone()).or_insert(match value {
+            FieldValue::Text(_) => FieldKind::Category,
+            FieldValue::Number(_) => FieldKind::Number,
+            FieldValue::Date(_) => FieldKind::Date,
+        });
+    }
+    Ok(fields
+        .into_iter()
+        .map(|(name, kind)| FieldInfo { name, kind })
+        .collect())
+}
+
+/// Names of the fixed columns of file tables, which custom fields can't use
+#[cfg(feature = "server")]
+const RESERVED_FIELD_NAMES: [&str; 10] = [
+    "Name",
+    "Type",
+    "Size",
+    "Modified",
+    "FHIR",
+    "Tags",
+    "Notes",
+    "SHA-256",
+    "MD5",
+    "Integrity",
+];
+
+#[cfg(feature = "server")]
+fn update_file_metadata(
+    path: &str,
+    name: &str,
+    f: impl FnOnce(&mut FileMetadata),
+) -> Result<(), ServerFnError> {
+    crate::server::check_file_name(name).map_err(ServerFnError::new)?;
+    let dir = crate::server::resolve_path(path).map_err(ServerFnError::new)?;
+    // Metadata of a file that doesn't exist would never be shown
+    if !dir.join(name).exists() {
+        return Err(ServerFnError::new(format!("{name} not found")));
+    }
+    crate::server::update_metadata(&crate::server::join_path(path, name), f)
+        .map_err(ServerFnError::new)
+}
+
+#[server]
+pub async fn get_views() -> Result<Vec<View>, ServerFnError> {
+    crate::server::load_views().map_err(ServerFnError::new)
+}
+
+/// Save a view, replacing any existing view with the same name
+#[server]
+pub async fn save_view(view: View) -> Result<(), ServerFnError> {
+    crate::server::update_views(
+        |views| match views.iter_mut().find(|v| v.name == view.name) {
+            Some(existing) => *existing = view,
+            None => views.push(view),
+        },
+    )
+    .map_err(ServerFnError::new)
+}
+
+#[server]
+pub async fn delete_view(name: String) -> Result<(), ServerFnError> {
+    crate::server::update_views(|views| views.retain(|v| v.name != name))
+        .map_err(ServerFnError::new)
+}
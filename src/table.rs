@@ -1,15 +1,122 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use dioxus::prelude::*;
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::export::{self, ExportFormat};
+
+mod collation;
+mod engine;
+mod fuzzy;
+
+pub use collation::Collation;
+
+#[cfg(feature = "server")]
+pub use engine::view_rows;
+use engine::{ColumnKind, Group, TableState, footer_aggregates, value_counts};
 
 #[derive(Props, Clone, PartialEq)]
 pub struct TableProps {
+    /// Identifies this table in row drops and scopes its DOM ids. Generated if not set.
+    #[props(into)]
+    pub id: Option<String>,
     pub columns: Vec<Column>,
-    pub data: Vec<Vec<String>>,
+    pub data: ReadOnlySignal<Vec<Vec<String>>>,
     pub ondetail: EventHandler<usize>,
+    /// Renders a panel with details of the row with the given index in `data`. Rows can be expanded
+    /// to show it below them if set.
+    pub row_details: Option<Callback<usize, Element>>,
+    /// Arranges the rows in a tree, by index in `data`. The table is flat if empty. Children loaded
+    /// later should be appended to `data`, so the indices of existing rows stay the same.
+    #[props(default)]
+    pub tree: ReadOnlySignal<Vec<TreeNode>>,
+    /// Called with the row index when an expandable row without children is expanded, to load them.
+    /// The row shows a loading indicator until the returned future resolves and collapses again on error.
+    pub onexpandrow: Option<Callback<usize, LoadResult>>,
+    /// Actions in the context menu of rows, opened by right-clicking a row or pressing the
+    /// context menu key or Shift+F10. Rows have no context menu if empty. With `row_details`, the
+    /// menu ends with Properties, which shows the details panel of the row.
+    #[props(default)]
+    pub row_actions: Vec<RowAction>,
+    /// Actions on all selected rows, shown in the toolbar while rows are selected
+    #[props(default)]
+    pub selection_actions: Vec<SelectionAction>,
+    /// Show a footer row with aggregates of the filtered rows
+    #[props(default)]
+    pub footer: bool,
+    /// Called when the user edits a cell of an editable column. The new value is shown right away
+    /// and rolled back if the returned future resolves to an error.
+    pub oncelledit: Option<Callback<CellEdit, EditResult>>,
+    /// Allow dragging rows out of this table into other tables
+    #[props(default)]
+    pub draggable_rows: bool,
+    /// Called when rows from another table are dropped onto this table. Dropping is disabled if not set.
+    pub onrowsdrop: Option<EventHandler<RowsDrop>>,
+    /// Saved views the user can switch between
+    #[props(default)]
+    pub views: Vec<View>,
+    /// Called when the user saves the current layout as a view. Saving is disabled if not set.
+    pub onsaveview: Option<EventHandler<View>>,
+    /// Called with the view name when the user deletes a view. Deleting is disabled if not set.
+    pub ondeleteview: Option<EventHandler<String>>,
+    /// Exports tables with more than `export::SERVER_EXPORT_THRESHOLD` rows on the server, given
+    /// the format and the current view. All tables are exported in the browser if not set.
+    pub server_export: Option<Callback<(ExportFormat, View)>>,
+}
+
+/// A named snapshot of the column layout, sort order and filters of a table
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct View {
+    pub name: String,
+    /// Visible columns in display order
+    pub columns: Vec<String>,
+    /// Column explicitly chosen for sorting, empty for the default order
+    pub sort_by: String,
+    pub sort_ascending: bool,
+    #[serde(default)]
+    pub search_text: String,
+    #[serde(default)]
+    pub fuzzy_search: bool,
+    /// Column-specific filter text by column name
+    #[serde(default)]
+    pub column_search_text: BTreeMap<String, String>,
+    /// Selected categories by column name
+    #[serde(default)]
+    pub column_category_filter: BTreeMap<String, BTreeSet<String>>,
+    #[serde(default)]
+    pub column_widths: BTreeMap<String, ColumnWidth>,
+    #[serde(default)]
+    pub pinned_columns: BTreeMap<String, Pin>,
+    #[serde(default)]
+    pub group_by: Option<String>,
+}
+
+/// Width of a column set by the user. Columns without a width are sized automatically.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ColumnWidth {
+    /// Width in pixels, set by dragging the resize handle
+    Fixed(f64),
+    /// As wide as the widest cell, set by double-clicking the resize handle
+    FitContent,
+}
+
+/// Side of the table a column is pinned to while scrolling horizontally
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Pin {
+    Left,
+    Right,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct ResizeState {
+    column: String,
+    start_x: f64,
+    start_width: f64,
 }
 
+const MIN_COLUMN_WIDTH: f64 = 48.0;
+
 #[derive(Clone, PartialEq, Debug)]
 enum DragState {
     None,
@@ -21,128 +128,600 @@ enum DragState {
 #[derive(Clone, PartialEq, Debug)]
 pub struct Column {
     name: String,
+    kind: ColumnKind,
+    /// Ordering of cells in text columns
+    collation: Collation,
     categorical: bool,
+    /// Cells hold several values separated by commas
+    list: bool,
     hidden: bool,
+    render: Option<Callback<Cell, Element>>,
+    editable: bool,
+    validate: Option<Callback<String, Result<(), String>>>,
+}
+
+/// Position of a row in a tree
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TreeNode {
+    /// Index of the parent row in `TableProps::data`, `None` for top-level rows
+    pub parent: Option<usize>,
+    /// Whether the row can have children and can be expanded
+    pub expandable: bool,
+}
+
+/// Rows dragged from one table onto another
+#[derive(Clone, PartialEq, Debug)]
+pub struct RowsDrop {
+    /// Id of the table the rows were dragged from
+    pub source: String,
+    /// Indices of the rows in the data of the source table
+    pub rows: Vec<usize>,
+    /// The dragged rows as in the data of the source table
+    pub values: Vec<Vec<String>>,
+}
+
+/// Rows currently being dragged, shared between all tables on the page
+static DRAGGED_ROWS: GlobalSignal<Option<RowsDrop>> = Signal::global(|| None);
+
+/// An edit of a cell by the user
+#[derive(Clone, PartialEq, Debug)]
+pub struct CellEdit {
+    /// Index of the row in `TableProps::data`
+    pub row: usize,
+    pub column: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// Outcome of saving a cell edit, with an error message to show on failure
+pub type EditResult = std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), String>>>>;
+
+/// Outcome of loading the children of a row, with an error message to show on failure
+pub type LoadResult = std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), String>>>>;
+
+#[derive(Clone, PartialEq, Debug)]
+struct EditState {
+    row: usize,
+    column: String,
+    /// Value before the edit
+    value: String,
+    draft: String,
+    /// Validation error of the draft
+    error: Option<String>,
+}
+
+/// An entry of the context menu of rows
+#[derive(Clone, PartialEq)]
+pub struct RowAction {
+    label: String,
+    /// Called with the index of the row in `TableProps::data`
+    onselect: Callback<usize>,
+    /// Whether the action is shown for a row. Shown for all rows if not set.
+    enabled: Option<Callback<usize, bool>>,
+}
+
+impl RowAction {
+    pub fn new<Marker, MaybeAsync: dioxus::dioxus_core::SpawnIfAsync<Marker>>(
+        label: &str,
+        onselect: impl FnMut(usize) -> MaybeAsync + 'static,
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            onselect: Callback::new(onselect),
+            enabled: None,
+        }
+    }
+
+    /// Only show the action for rows for which the given function returns true
+    pub fn enabled(mut self, enabled: impl FnMut(usize) -> bool + 'static) -> Self {
+        self.enabled = Some(Callback::new(enabled));
+        self
+    }
+}
+
+/// An action on the selected rows
+#[derive(Clone, PartialEq)]
+pub struct SelectionAction {
+    label: String,
+    /// Called with the indices of the selected rows in `TableProps::data`, in ascending order
+    onselect: Callback<Vec<usize>>,
+}
+
+impl SelectionAction {
+    pub fn new<Marker, MaybeAsync: dioxus::dioxus_core::SpawnIfAsync<Marker>>(
+        label: &str,
+        onselect: impl FnMut(Vec<usize>) -> MaybeAsync + 'static,
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            onselect: Callback::new(onselect),
+        }
+    }
+}
+
+/// A cell passed to a custom cell renderer
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cell {
+    /// Index of the row in `TableProps::data`
+    pub row: usize,
+    /// The underlying value, as used for sorting, filtering and export
+    pub value: String,
+    /// Positions of the characters of the value matched by the search, for use with `Highlight`
+    pub highlights: Vec<usize>,
+}
+
+/// Text with the characters at the given positions highlighted, e.g. the matches of a search
+#[component]
+pub fn Highlight(text: String, positions: Vec<usize>) -> Element {
+    let chunks = text
+        .chars()
+        .enumerate()
+        .chunk_by(|(i, _)| positions.contains(i))
+        .into_iter()
+        .map(|(matched, chars)| (matched, chars.map(|(_, c)| c).collect::<String>()))
+        .collect::<Vec<_>>();
+    rsx! {
+        for (matched , chunk) in chunks {
+            if matched {
+                mark { class: "bg-yellow-200", "{chunk}" }
+            } else {
+                "{chunk}"
+            }
+        }
+    }
 }
 
 impl Column {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            kind: ColumnKind::Text,
+            collation: Collation::default(),
             categorical: false,
+            list: false,
             hidden: false,
+            render: None,
+            editable: false,
+            validate: None,
         }
     }
 
+    /// Cells are decimal numbers
+    pub fn numeric(mut self) -> Self {
+        self.kind = ColumnKind::Number;
+        self
+    }
+
+    /// Cells are a number of bytes, displayed in human-readable units
+    pub fn bytes(mut self) -> Self {
+        self.kind = ColumnKind::Bytes;
+        self
+    }
+
+    /// Cells are RFC 3339 timestamps, displayed in the local time zone
+    pub fn date(mut self) -> Self {
+        self.kind = ColumnKind::Date;
+        self
+    }
+
+    /// How cells are ordered when sorting by this column. Only applies to text columns.
+    pub fn collation(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+
     pub fn categorical(mut self) -> Self {
         self.categorical = true;
         self
     }
 
+    /// Cells hold several values separated by commas, like tags. Category filters match cells
+    /// containing any selected value, and the footer counts each value.
+    pub fn list(mut self) -> Self {
+        self.list = true;
+        self
+    }
+
     pub fn hidden(mut self) -> Self {
         self.hidden = true;
         self
     }
+
+    /// Allow editing cells of this column by double-clicking or pressing Enter
+    pub fn editable(mut self) -> Self {
+        self.editable = true;
+        self
+    }
+
+    /// Check edited values before they are saved, returning an error message if a value is invalid
+    pub fn validate(
+        mut self,
+        validate: impl FnMut(String) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.validate = Some(Callback::new(validate));
+        self
+    }
+
+    /// Render cells of this column with a custom function instead of as text
+    pub fn render(mut self, render: impl FnMut(Cell) -> Element + 'static) -> Self {
+        self.render = Some(Callback::new(render));
+        self
+    }
+}
+
+/// Compute the sticky positioning style of each pinned column. Pinned columns stick next to the
+/// selection column on the left or the detail column on the right, after any columns pinned before them.
+fn pinned_styles(
+    columns: &[String],
+    pins: &BTreeMap<String, Pin>,
+    measured_widths: &HashMap<String, f64>,
+    edge_widths: (f64, f64),
+) -> HashMap<String, String> {
+    // Each grid cell is followed by a 1px gap
+    let width = |c: &String| measured_widths.get(c).copied().unwrap_or_default() + 1.0;
+    let mut styles = HashMap::new();
+    let mut left = edge_widths.0 + 1.0;
+    for c in columns.iter().filter(|c| pins.get(*c) == Some(&Pin::Left)) {
+        styles.insert(
+            c.clone(),
+            format!("position: sticky; left: {left}px; z-index: 1;"),
+        );
+        left += width(c);
+    }
+    let mut right = edge_widths.1 + 1.0;
+    for c in columns
+        .iter()
+        .rev()
+        .filter(|c| pins.get(*c) == Some(&Pin::Right))
+    {
+        styles.insert(
+            c.clone(),
+            format!("position: sticky; right: {right}px; z-index: 1;"),
+        );
+        right += width(c);
+    }
+    styles
 }
 
 #[component]
 pub fn Table(props: TableProps) -> Element {
-    let columns = use_signal(|| props.columns.clone());
-    let mut search_text = use_signal(|| "".to_string());
-    let mut custom_columns = use_signal(|| {
-        props
-            .columns
-            .iter()
-            .filter(|c| !c.hidden)
-            .map(|c| c.name.clone())
-            .collect::<Vec<_>>()
+    // Prefix for DOM ids and anchor names, so several tables can be on the same page
+    let scope = use_signal(|| {
+        props.id.clone().unwrap_or_else(|| {
+            format!(
+                "table-{}",
+                current_scope_id().map(|id| id.0).unwrap_or_default()
+            )
+        })
     });
-    let mut sort_by = use_signal(|| props.columns[0].name.clone());
-    let mut sort_ascending = use_signal(|| true);
-    let mut column_search_text = use_signal(|| vec![String::new(); props.columns.len()]);
-    let mut column_category_filter =
-        use_signal(|| vec![HashSet::<String>::new(); props.columns.len()]);
+    // Updated when the caller passes new columns, e.g. with renderers for new data
+    let columns = use_memo(use_reactive(&props.columns, |columns| columns));
+    let mut state = use_signal(|| TableState::new(&props.columns));
     let mut drag_state = use_signal(|| DragState::None);
+    let mut current_view = use_signal(|| None::<String>);
+    let mut new_view_name = use_signal(|| "".to_string());
+    let mut resize_state = use_signal(|| None::<ResizeState>);
+    // Rendered widths of the header cells, used to position pinned columns
+    let mut measured_widths = use_signal(HashMap::<String, f64>::new);
+    let mut select_width = use_signal(|| 0.0);
+    let mut detail_width = use_signal(|| 0.0);
+    // Position of the keyboard-focusable cell. Row 0 is the header row, column 0 the selection column.
+    let mut focused_cell = use_signal(|| (0, 1));
+    // Whether a cell itself has focus, as opposed to a control inside it
+    let mut cell_has_focus = use_signal(|| false);
+    let mut selected_rows = use_signal(HashSet::<usize>::new);
+    // Rows showing their details panel
+    let mut expanded_rows = use_signal(HashSet::<usize>::new);
+    // Expanded rows in tree mode
+    let mut expanded_nodes = use_signal(HashSet::<usize>::new);
+    // Expanded rows whose children are being loaded by the caller
+    let mut loading_nodes = use_signal(HashSet::<usize>::new);
+    let mut editing = use_signal(|| None::<EditState>);
+    // Edited values by row and column index, shown until the caller updates the data
+    let mut cell_overrides = use_signal(HashMap::<usize, HashMap<usize, String>>::new);
+    let mut save_error = use_signal(|| None::<String>);
+    let mut start_edit = move |row: usize, column: &Column, value: &str| {
+        if column.editable {
+            editing.set(Some(EditState {
+                row,
+                column: column.name.clone(),
+                value: value.to_string(),
+                draft: value.to_string(),
+                error: None,
+            }));
+        }
+    };
+    let mut commit_edit = move || {
+        let Some(edit) = editing() else {
+            return;
+        };
+        let columns = columns.read();
+        let Some(idx) = columns.iter().position(|c| c.name == edit.column) else {
+            return;
+        };
+        if let Some(validate) = columns[idx].validate
+            && let Err(error) = validate.call(edit.draft.clone())
+        {
+            editing.set(Some(EditState {
+                error: Some(error),
+                ..edit
+            }));
+            return;
+        }
+        editing.set(None);
+        if edit.draft == edit.value {
+            return;
+        }
+        // Show the new value right away and roll back if saving fails
+        cell_overrides.with_mut(|overrides| {
+            overrides
+                .entry(edit.row)
+                .or_default()
+                .insert(idx, edit.draft.clone());
+        });
+        if let Some(oncelledit) = props.oncelledit {
+            let result = oncelledit.call(CellEdit {
+                row: edit.row,
+                column: edit.column.clone(),
+                old_value: edit.value.clone(),
+                new_value: edit.draft.clone(),
+            });
+            spawn(async move {
+                if let Err(error) = result.await {
+                    cell_overrides.with_mut(|overrides| {
+                        if let Some(value) =
+                            overrides.get_mut(&edit.row).and_then(|o| o.get_mut(&idx))
+                            && *value == edit.draft
+                        {
+                            *value = edit.value.clone();
+                        }
+                    });
+                    save_error.set(Some(format!("Failed to save {}: {error}", edit.column)));
+                }
+            });
+        }
+    };
+    let mut row_drop_target = use_signal(|| false);
+    // Row the context menu was opened for, by index in the data and row number in the grid
+    let mut context_menu = use_signal(|| None::<(usize, usize)>);
+    use_effect(move || {
+        if context_menu().is_some() {
+            // Show the menu once it has been rendered for the row
+            document::eval(&format!(
+                "const menu = document.getElementById('{scope}-context-menu');
+                menu.showPopover();
+                if (window.CSSAnchorPositioning) window.CSSAnchorPositioning();
+                menu.querySelector('[role=menuitem]')?.focus();"
+            ));
+        }
+    });
+    use_effect(move || {
+        // Forget columns the caller no longer passes, e.g. custom fields that were removed
+        let columns = columns.read();
+        if !state.peek().has_only(&columns) {
+            state.write().retain_columns(&columns);
+        }
+    });
+    use_effect(move || {
+        // Row indices refer to the old data once the caller replaces it
+        props.data.read();
+        cell_overrides.set(HashMap::new());
+        selected_rows.set(HashSet::new());
+        expanded_rows.set(HashSet::new());
+    });
+    let mut toggle_node = move |id: usize| {
+        if expanded_nodes.write().remove(&id) {
+            return;
+        }
+        expanded_nodes.write().insert(id);
+        // Load the children of rows expanded for the first time
+        let loaded = props.tree.read().iter().any(|node| node.parent == Some(id));
+        if let Some(onexpandrow) = props.onexpandrow
+            && !loaded
+            && loading_nodes.write().insert(id)
+        {
+            let result = onexpandrow.call(id);
+            spawn(async move {
+                if let Err(error) = result.await {
+                    expanded_nodes.write().remove(&id);
+                    save_error.set(Some(format!("Failed to load rows: {error}")));
+                }
+                loading_nodes.write().remove(&id);
+            });
+        }
+    };
+    // Nesting level of a row in tree mode, 0 for top-level rows
+    let depth = move |id: usize| {
+        let tree = props.tree.read();
+        std::iter::successors(tree.get(id).and_then(|node| node.parent), |parent| {
+            tree.get(*parent).and_then(|node| node.parent)
+        })
+        .take(tree.len())
+        .count()
+    };
+    let tree_mode = !props.tree.read().is_empty();
+    let has_row_actions = !props.row_actions.is_empty();
+    let mut toggle_expanded = move |id: usize| {
+        expanded_rows.with_mut(|expanded| {
+            if !expanded.remove(&id) {
+                expanded.insert(id);
+            }
+        });
+    };
+    let mut toggle_selected = move |id: usize| {
+        selected_rows.with_mut(|selected| {
+            if !selected.remove(&id) {
+                selected.insert(id);
+            }
+        });
+    };
+    let mut focus_cell = move |row: usize, col: usize| {
+        focused_cell.set((row, col));
+        document::eval(&format!(
+            "document.getElementById('{scope}-cell-{row}-{col}')?.focus()"
+        ));
+    };
+    let pin_styles = use_memo(move || {
+        let state = state.read();
+        pinned_styles(
+            &state.columns,
+            &state.pinned_columns,
+            &measured_widths.read(),
+            (select_width(), detail_width()),
+        )
+    });
     use_effect(move || {
         // Run this effect when drag_state changes
         drag_state();
         // Rerun the anchor positioning polyfill
         document::eval("if (window.CSSAnchorPositioning) window.CSSAnchorPositioning()");
     });
-    let cloned_data = props.data.clone();
+    let grid_tracks = state
+        .read()
+        .columns
+        .iter()
+        .map(|c| match state.read().column_widths.get(c) {
+            Some(ColumnWidth::Fixed(width)) => format!("{width}px"),
+            Some(ColumnWidth::FitContent) => "max-content".to_string(),
+            None => "auto".to_string(),
+        })
+        .join(" ");
     let filtered_data = use_memo(move || {
-        let mut data = cloned_data
+        let overrides = cell_overrides.read();
+        let data = props.data.read();
+        // Apply edits the caller hasn't reflected in the data yet
+        let rows = engine::apply_edits(&data, &overrides);
+        let tree = props.tree.read();
+        if tree.is_empty() {
+            state.read().rows(&columns.read(), rows)
+        } else {
+            state
+                .read()
+                .tree_rows(&columns.read(), rows, &tree, &expanded_nodes.read())
+        }
+    });
+    let groups = use_memo(move || {
+        if props.tree.read().is_empty() {
+            let overrides = cell_overrides.read();
+            let data = props.data.read();
+            state.read().groups(
+                &columns.read(),
+                engine::apply_edits(&data, &overrides),
+                filtered_data(),
+            )
+        } else {
+            // Grouping would tear rows apart from their parents
+            vec![Group::ungrouped(filtered_data())]
+        }
+    });
+    // The rows that are currently rendered, i.e. not inside a collapsed group
+    let visible_rows = use_memo(move || {
+        groups()
+            .into_iter()
+            .filter(|g| !g.collapsed)
+            .flat_map(|g| g.rows)
+            .collect::<Vec<_>>()
+    });
+    use_effect(move || {
+        // Keep the focusable cell inside the grid when rows or columns disappear
+        let last_row = visible_rows.read().len();
+        let last_col = state.read().columns.len() + 1;
+        let (row, col) = *focused_cell.peek();
+        if row > last_row || col > last_col {
+            focused_cell.set((row.min(last_row), col.min(last_col)));
+        }
+    });
+    let display_columns = state
+        .read()
+        .columns
+        .iter()
+        .filter_map(|header| columns.read().iter().find(|c| &c.name == header).cloned())
+        .collect::<Vec<_>>();
+    let editable_columns = display_columns.clone();
+    // Sticky position of pinned columns and, in tree mode, indentation of the first column by nesting level
+    let cell_style = move |column: &str, id: usize, c: usize| {
+        let mut style = pin_styles().get(column).cloned().unwrap_or_default();
+        if tree_mode && c == 1 {
+            style.push_str(&format!(
+                " padding-left: {}rem;",
+                0.5 + 1.25 * depth(id) as f64
+            ));
+        }
+        style
+    };
+    let sort_column = state
+        .read()
+        .sort_column(&columns.read())
+        .map(str::to_string);
+    // Row numbers of the first row in each group, counting only rows outside collapsed groups
+    let mut next_row = 1;
+    let groups_with_start = groups()
+        .into_iter()
+        .map(|group| {
+            let start = next_row;
+            if !group.collapsed {
+                next_row += group.rows.len();
+            }
+            (group, start)
+        })
+        .collect::<Vec<_>>();
+    // Aggregates and value counts of the visible columns over the filtered rows
+    let footer_stats = if props.footer {
+        let filtered_data = filtered_data.read();
+        display_columns
             .iter()
             .enumerate()
-            .filter(|(_, row)| {
-                // Filter rows based on search text
-                let search_text = search_text.read().to_lowercase();
-                row.iter()
-                    .any(|cell| cell.to_lowercase().contains(&search_text))
-            })
-            .filter(|(_, row)| {
-                // Filter rows based on column-specific search text
-                row.iter().enumerate().all(|(i, cell)| {
-                    let filter_text = column_search_text
-                        .read()
-                        .get(i)
-                        .unwrap_or(&String::new())
-                        .to_lowercase();
-                    let category_filter = column_category_filter.get(i).unwrap();
-                    (filter_text.is_empty() && category_filter.is_empty())
-                        || (!filter_text.is_empty() && cell.to_lowercase().contains(&filter_text))
-                        || (!category_filter.is_empty() && category_filter.contains(cell))
-                })
-            })
-            .sorted_by_key(|(_, row)| {
-                // Sort by the column specified in sort_by
-                let idx = columns
-                    .read()
-                    .iter()
-                    .position(|h| &h.name == &sort_by())
-                    .unwrap_or(0);
-                row.get(idx).cloned().unwrap_or_default()
-            })
-            .map(|(id, row)| {
-                // Collect only the custom columns
-                (
-                    id,
-                    custom_columns
-                        .read()
+            .map(|(i, column)| {
+                let cells = || {
+                    filtered_data
                         .iter()
-                        .filter_map(|header| {
-                            columns
-                                .read()
-                                .iter()
-                                .position(|h| &h.name == header)
-                                .and_then(|idx| row.get(idx).cloned())
-                        })
-                        .collect::<Vec<_>>(),
+                        .map(|(_, row)| row.get(i).map_or("", String::as_str))
+                };
+                (
+                    footer_aggregates(column.kind, cells()),
+                    value_counts(cells().flat_map(|cell| column.values(cell)), 5),
                 )
             })
-            .collect::<Vec<_>>();
-        if !sort_ascending() {
-            data.reverse();
-        }
-        data
-    });
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
     rsx! {
         div { class: "m-4 flex items-center gap-2",
             input {
                 class: "border border-gray-300 rounded p-1",
                 placeholder: "Search table...",
-                value: "{search_text}",
-                oninput: move |event| search_text.set(event.value()),
+                value: "{state.read().search_text}",
+                oninput: move |event| state.write().search_text = event.value(),
+            }
+            label {
+                class: "flex items-center gap-1",
+                title: "Tolerate typos and words in any order, showing the best matches first",
+                input {
+                    r#type: "checkbox",
+                    checked: state.read().fuzzy_search,
+                    onchange: move |event| state.write().fuzzy_search = event.checked(),
+                }
+                "Fuzzy"
+            }
+            if state.read().fuzzy_search && state.read().sort_by.is_some() {
+                button {
+                    class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                    onclick: move |_| state.write().clear_sort(),
+                    "Sort by relevance"
+                }
             }
             button {
-                class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200 [anchor-name:--customize-button]",
-                popovertarget: "customize-popover",
+                class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                style: "anchor-name: --{scope}-customize-button",
+                popovertarget: "{scope}-customize-popover",
                 "Customize Columns"
             }
             div {
                 // The anchor positioning polyfill requires inset-auto for whatever reason
-                class: "border border-gray-300 rounded shadow-md p-2 absolute [position-anchor:--customize-button] [position-area:bottom_center] inset-auto",
-                id: "customize-popover",
+                class: "border border-gray-300 rounded shadow-md p-2 absolute [position-area:bottom_center] inset-auto",
+                style: "position-anchor: --{scope}-customize-button",
+                id: "{scope}-customize-popover",
                 popover: "auto",
                 for header in props.columns.iter().cloned() {
                     label {
@@ -150,286 +729,973 @@ pub fn Table(props: TableProps) -> Element {
                         class: "flex items-center gap-2",
                         input {
                             r#type: "checkbox",
-                            checked: custom_columns().contains(&header.name),
+                            checked: state.read().columns.contains(&header.name),
                             onchange: {
                                 let header = header.clone();
-                                move |_| {
-                                    custom_columns
-                                        .with_mut(|vec| {
-                                            if vec.contains(&header.name) {
-                                                vec.retain(|h| h != &header.name);
-                                            } else {
-                                                let pos = columns
-                                                    .read()
-                                                    .iter()
-                                                    .position(|h| h.name == header.name)
-                                                    .unwrap();
-                                                let mut insert_at = vec.len();
-                                                for (i, h) in vec.iter().enumerate() {
-                                                    if let Some(col_pos) = columns
-                                                        .read()
-                                                        .iter()
-                                                        .position(|c| &c.name == h)
-                                                    {
-                                                        if col_pos > pos {
-                                                            insert_at = i;
-                                                            break;
-                                                        }
-                                                    }
-                                                }
-                                                vec.insert(insert_at, header.name.clone());
-                                            }
-                                        });
-                                }
+                                move |_| state.write().toggle_column(&columns.read(), &header.name)
                             },
                         }
-                        span { "{header.name}" }
+                        span { class: "grow", "{header.name}" }
+                        // Pin buttons
+                        for (pin , label , title) in [(Pin::Left, "⇤", "Pin left"), (Pin::Right, "⇥", "Pin right")] {
+                            button {
+                                class: "px-1 rounded hover:bg-gray-200",
+                                class: if state.read().pinned_columns.get(&header.name) == Some(&pin) { "text-blue-500" } else { "text-gray-400" },
+                                title,
+                                onclick: {
+                                    let header = header.clone();
+                                    move |event: Event<MouseData>| {
+                                        // Don't toggle the checkbox of the surrounding label
+                                        event.prevent_default();
+                                        state.write().toggle_pin(&header.name, pin);
+                                    }
+                                },
+                                "{label}"
+                            }
+                        }
                     }
                 }
                 // Reset columns button
                 button {
                     class: "border border-gray-300 rounded px-2 py-1 mt-2 bg-gray-100 hover:bg-gray-200 text-sm",
-                    onclick: move |_| {
-                        custom_columns
-                            .set(
-                                props
-                                    .columns
-                                    .iter()
-                                    .filter(|c| !c.hidden)
-                                    .map(|c| c.name.clone())
-                                    .collect(),
-                            );
-                    },
+                    onclick: move |_| state.write().reset_columns(&columns.read()),
                     "Reset Columns"
                 }
             }
-        }
-        div {
-            class: "grid gap-px p-px m-4",
-            style: "grid-template-columns: max-content repeat({custom_columns().len()}, auto) max-content",
-            div { class: "grid grid-cols-subgrid col-span-full",
-                div { class: "outline outline-gray-300 px-2 py-1 bg-gray-100" }
-                for (i , idx , header) in custom_columns()
-                    .iter()
-                    .enumerate()
-                    .map(|(i, header)| (
-                        i,
-                        columns().iter().position(|c| &c.name == header).unwrap(),
-                        header,
-                    ))
-                {
-                    div {
-                        key: "{header}",
-                        class: "outline outline-gray-300 bg-gray-100 flex",
-                        style: "anchor-name: --header-{i+1}",
-                        // To make only the handle draggable, we need to set the draggable attribute conditionally
-                        // https://stackoverflow.com/questions/26283661/drag-drop-with-handle
-                        draggable: drag_state() == DragState::Mousedown(i),
-                        ondragstart: move |_| {
-                            if drag_state() == DragState::Mousedown(i) {
-                                drag_state.set(DragState::Dragging(i));
+            if !selected_rows.read().is_empty() {
+                for action in props.selection_actions.iter() {
+                    button {
+                        key: "{action.label}",
+                        class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                        onclick: {
+                            let onselect = action.onselect;
+                            move |_| onselect(selected_rows.read().iter().copied().sorted().collect())
+                        },
+                        "{action.label} ({selected_rows.read().len()})"
+                    }
+                }
+            }
+            button {
+                class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                style: "anchor-name: --{scope}-export-button",
+                popovertarget: "{scope}-export-popover",
+                "Export"
+            }
+            div {
+                // The anchor positioning polyfill requires inset-auto for whatever reason
+                class: "border border-gray-300 rounded shadow-md p-2 absolute [position-area:bottom_center] inset-auto",
+                style: "position-anchor: --{scope}-export-button",
+                id: "{scope}-export-popover",
+                popover: "auto",
+                for format in ExportFormat::ALL {
+                    button {
+                        key: "{format.extension()}",
+                        class: "block w-full text-left px-2 py-1 hover:bg-gray-100",
+                        onclick: {
+                            let server_export = props.server_export;
+                            move |_| {
+                                document::eval(&format!("document.getElementById('{scope}-export-popover').hidePopover()"));
+                                // Export exactly what is visible: the custom columns of the filtered rows
+                                let headers = state.read().columns.clone();
+                                let rows = filtered_data().into_iter().map(|(_, row)| row).collect::<Vec<_>>();
+                                if let Some(server_export) = server_export
+                                    && rows.len() > export::SERVER_EXPORT_THRESHOLD
+                                {
+                                    server_export((format, state.read().to_view(String::new())));
+                                    return;
+                                }
+                                match export::export(format, &headers, &rows) {
+                                    Ok(bytes) => {
+                                        export::download(
+                                            &format!("export.{}", format.extension()),
+                                            format.mime_type(),
+                                            &bytes,
+                                        )
+                                    }
+                                    Err(e) => tracing::error!("Failed to export table: {e:#}"),
+                                }
                             }
                         },
-                        ondragend: move |_| {
-                            drag_state.set(DragState::None);
+                        "{format.label()}"
+                    }
+                }
+            }
+            if !tree_mode {
+                select {
+                    class: "border border-gray-300 rounded p-1",
+                    "aria-label": "Group by",
+                    onchange: move |event: Event<FormData>| {
+                        let value = event.value();
+                        state.write().set_group_by(if value.is_empty() { None } else { Some(value) });
+                    },
+                    option { value: "", selected: state.read().group_by.is_none(), "No grouping" }
+                    for column in props.columns.iter() {
+                        option {
+                            key: "{column.name}",
+                            value: "{column.name}",
+                            selected: state.read().group_by.as_ref() == Some(&column.name),
+                            "Group by {column.name}"
+                        }
+                    }
+                }
+            }
+            if props.row_details.is_some() {
+                button {
+                    class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                    onclick: move |_| expanded_rows.set(filtered_data.read().iter().map(|(id, _)| *id).collect()),
+                    "Expand All"
+                }
+                button {
+                    class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                    onclick: move |_| expanded_rows.set(HashSet::new()),
+                    "Collapse All"
+                }
+            }
+            // View selection
+            if !props.views.is_empty() {
+                select {
+                    class: "border border-gray-300 rounded p-1",
+                    onchange: {
+                        let views = props.views.clone();
+                        move |event: Event<FormData>| {
+                            if let Some(view) = views.iter().find(|v| v.name == event.value()) {
+                                state.write().apply_view(&columns.read(), view);
+                                current_view.set(Some(view.name.clone()));
+                            }
+                        }
+                    },
+                    option {
+                        value: "",
+                        disabled: true,
+                        selected: current_view().is_none(),
+                        "Select view..."
+                    }
+                    for view in props.views.iter() {
+                        option {
+                            key: "{view.name}",
+                            value: "{view.name}",
+                            selected: current_view().as_ref() == Some(&view.name),
+                            "{view.name}"
+                        }
+                    }
+                }
+            }
+            if let (Some(ondeleteview), Some(name)) = (props.ondeleteview, current_view()) {
+                if props.views.iter().any(|v| v.name == name) {
+                    button {
+                        class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                        onclick: move |_| {
+                            ondeleteview(name.clone());
+                            current_view.set(None);
+                        },
+                        "Delete View"
+                    }
+                }
+            }
+            if let Some(onsaveview) = props.onsaveview {
+                button {
+                    class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200",
+                    style: "anchor-name: --{scope}-save-view-button",
+                    popovertarget: "{scope}-save-view-popover",
+                    "Save View"
+                }
+                div {
+                    // The anchor positioning polyfill requires inset-auto for whatever reason
+                    class: "border border-gray-300 rounded shadow-md p-2 absolute [position-area:bottom_center] inset-auto",
+                    style: "position-anchor: --{scope}-save-view-button",
+                    id: "{scope}-save-view-popover",
+                    popover: "auto",
+                    form {
+                        class: "flex items-center gap-2",
+                        onsubmit: move |event| {
+                            event.prevent_default();
+                            let name = new_view_name().trim().to_string();
+                            if name.is_empty() {
+                                return;
+                            }
+                            onsaveview(state.read().to_view(name.clone()));
+                            current_view.set(Some(name));
+                            new_view_name.set(String::new());
+                            document::eval(&format!("document.getElementById('{scope}-save-view-popover').hidePopover()"));
                         },
-                        span { class: "font-bold px-2 py-1", "{header}" }
-                        // Sort button
+                        input {
+                            class: "border border-gray-300 rounded p-1",
+                            placeholder: "View name",
+                            value: "{new_view_name}",
+                            oninput: move |event| new_view_name.set(event.value()),
+                        }
+                        button {
+                            class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200 text-sm",
+                            r#type: "submit",
+                            "Save"
+                        }
+                    }
+                }
+            }
+        }
+        // Overlay capturing mouse movement anywhere on the page while a column is resized
+        if let Some(resize) = resize_state() {
+            div {
+                class: "fixed inset-0 z-10 cursor-col-resize",
+                onmousemove: move |event| {
+                    let width = resize.start_width + event.client_coordinates().x - resize.start_x;
+                    state
+                        .write()
+                        .column_widths
+                        .insert(resize.column.clone(), ColumnWidth::Fixed(width.max(MIN_COLUMN_WIDTH)));
+                },
+                onmouseup: move |_| resize_state.set(None),
+            }
+        }
+        if has_row_actions {
+            div {
+                // The anchor positioning polyfill requires inset-auto for whatever reason
+                class: "border border-gray-300 rounded shadow-md py-1 absolute min-w-40 [position-area:bottom_span-right] inset-auto",
+                style: "position-anchor: --{scope}-context-row",
+                id: "{scope}-context-menu",
+                popover: "auto",
+                role: "menu",
+                onkeydown: move |event| {
+                    let sibling = match event.key() {
+                        Key::ArrowDown => "nextElementSibling",
+                        Key::ArrowUp => "previousElementSibling",
+                        _ => return,
+                    };
+                    event.prevent_default();
+                    document::eval(&format!("document.activeElement?.{sibling}?.focus()"));
+                },
+                if let Some((id, r)) = context_menu() {
+                    for action in props.row_actions.iter().filter(|a| a.enabled.is_none_or(|enabled| enabled.call(id))) {
                         button {
-                            class: "ml-auto flex items-center px-1",
+                            key: "{action.label}",
+                            class: "block w-full text-left px-3 py-1 hover:bg-gray-100 focus:bg-gray-100",
+                            role: "menuitem",
                             onclick: {
-                                let header = header.clone();
+                                let onselect = action.onselect;
                                 move |_| {
-                                    if sort_by() == header.clone() {
-                                        sort_ascending.set(!sort_ascending());
-                                    } else {
-                                        sort_by.set(header.clone());
-                                        sort_ascending.set(true);
-                                    }
+                                    document::eval(&format!("document.getElementById('{scope}-context-menu').hidePopover()"));
+                                    context_menu.set(None);
+                                    focus_cell(r, focused_cell().1);
+                                    onselect(id);
                                 }
                             },
-                            svg {
-                                class: if sort_by() == header.clone() { if sort_ascending() { "text-blue-500" } else { "text-blue-500 rotate-180" } } else { "" },
-                                fill: "currentColor",
-                                width: "24",
-                                height: "24",
-                                xmlns: "http://www.w3.org/2000/svg",
-                                "viewBox": "0 -960 960 960",
-                                path { d: "M480-528 296-344l-56-56 240-240 240 240-56 56z" }
-                            }
+                            "{action.label}"
                         }
-                        // Filter button
+                    }
+                    if props.row_details.is_some() {
                         button {
-                            class: "flex items-center px-1 [anchor-name:filter-popover-{i}]",
-                            class: if !column_search_text()[idx].is_empty() || !column_category_filter()[idx].is_empty() { "text-blue-500" },
-                            popovertarget: "filter-popover-{i}",
-                            svg {
-                                fill: "currentColor",
-                                "viewBox": "0 -960 960 960",
-                                width: "24",
-                                xmlns: "http://www.w3.org/2000/svg",
-                                height: "24",
-                                path { d: "M440-160q-17 0-28.5-11.5T400-200v-240L168-736q-15-20-4.5-42t36.5-22h560q26 0 36.5 22t-4.5 42L560-440v240q0 17-11.5 28.5T520-160zm40-308 198-252H282zm0 0" }
+                            key: "Properties",
+                            class: "block w-full text-left px-3 py-1 hover:bg-gray-100 focus:bg-gray-100",
+                            role: "menuitem",
+                            onclick: move |_| {
+                                document::eval(&format!("document.getElementById('{scope}-context-menu').hidePopover()"));
+                                context_menu.set(None);
+                                focus_cell(r, focused_cell().1);
+                                expanded_rows.write().insert(id);
+                            },
+                            "Properties"
+                        }
+                    }
+                }
+            }
+        }
+        // Screen reader announcement of sort and filter changes
+        div { class: "sr-only", role: "status", "aria-live": "polite",
+            if let Some(column) = &sort_column {
+                "Sorted by {column} "
+                if state.read().sort_ascending {
+                    "ascending"
+                } else {
+                    "descending"
+                }
+            } else {
+                "Sorted by relevance"
+            }
+            ". Showing {filtered_data.read().len()} of {props.data.read().len()} rows."
+        }
+        if let Some(error) = save_error() {
+            div {
+                class: "m-4 p-2 border border-red-300 rounded bg-red-50 flex items-center gap-2",
+                role: "alert",
+                span { class: "grow", "{error}" }
+                button {
+                    class: "border border-gray-300 rounded px-2 py-1 bg-gray-100 hover:bg-gray-200 text-sm",
+                    onclick: move |_| save_error.set(None),
+                    "Dismiss"
+                }
+            }
+        }
+        div { class: "overflow-x-auto",
+            div {
+                class: "grid gap-px p-px m-4",
+                class: if row_drop_target() { "outline-2 outline-blue-500" },
+                style: "grid-template-columns: max-content {grid_tracks} max-content",
+                role: if tree_mode { "treegrid" } else { "grid" },
+                ondragover: move |event| {
+                    // Accept rows dragged from other tables
+                    if props.onrowsdrop.is_some()
+                        && DRAGGED_ROWS.read().as_ref().is_some_and(|drag| drag.source != scope())
+                    {
+                        event.prevent_default();
+                        row_drop_target.set(true);
+                    }
+                },
+                ondragleave: move |_| row_drop_target.set(false),
+                ondrop: move |event| {
+                    row_drop_target.set(false);
+                    if let Some(onrowsdrop) = props.onrowsdrop
+                        && let Some(drop) = DRAGGED_ROWS.write().take_if(|drag| drag.source != scope())
+                    {
+                        event.prevent_default();
+                        onrowsdrop(drop);
+                    }
+                },
+                "aria-rowcount": props.data.read().len() + 1,
+                "aria-colcount": state.read().columns.len() + 2,
+                "aria-multiselectable": "true",
+                onkeydown: move |event: Event<KeyboardData>| {
+                    if !cell_has_focus() {
+                        return;
+                    }
+                    let (row, col) = focused_cell();
+                    let last_row = visible_rows.read().len();
+                    let last_col = state.read().columns.len() + 1;
+                    let ctrl = event.modifiers().ctrl();
+                    let is_header_column = row == 0 && (1..last_col).contains(&col);
+                    match event.key() {
+                        // Move the focused column with Alt+Left/Right
+                        Key::ArrowLeft if event.modifiers().alt() && is_header_column && col > 1 => {
+                            state.write().move_column(col - 1, col - 2);
+                            focus_cell(row, col - 1);
+                        }
+                        Key::ArrowRight if event.modifiers().alt() && is_header_column
+                            && col < last_col - 1 => {
+                            state.write().move_column(col - 1, col);
+                            focus_cell(row, col + 1);
+                        }
+                        Key::ArrowUp => focus_cell(row.saturating_sub(1), col),
+                        Key::ArrowDown => focus_cell((row + 1).min(last_row), col),
+                        Key::ArrowLeft => focus_cell(row, col.saturating_sub(1)),
+                        Key::ArrowRight => focus_cell(row, (col + 1).min(last_col)),
+                        Key::Home if ctrl => focus_cell(0, 0),
+                        Key::End if ctrl => focus_cell(last_row, last_col),
+                        Key::Home => focus_cell(row, 0),
+                        Key::End => focus_cell(row, last_col),
+                        Key::Enter if is_header_column => {
+                            let column = state.read().columns[col - 1].clone();
+                            state.write().toggle_sort(&columns.read(), &column);
+                        }
+                        Key::Character(c) if c == " " && is_header_column => {
+                            let column = state.read().columns[col - 1].clone();
+                            state.write().toggle_sort(&columns.read(), &column);
+                        }
+                        Key::Character(c) if c == " " && row > 0 => {
+                            toggle_selected(visible_rows.read()[row - 1].0);
+                        }
+                        Key::Enter | Key::F2 if row > 0 && col > 0
+                            && editable_columns.get(col - 1).is_some_and(|c| c.editable) => {
+                            let (id, cells) = visible_rows.read()[row - 1].clone();
+                            if let Some(cell) = cells.get(col - 1) {
+                                start_edit(id, &editable_columns[col - 1], cell);
+                            }
+                        }
+                        // Expand and collapse rows in tree mode with + and -
+                        Key::Character(c) if c == "+" && row > 0 && tree_mode => {
+                            let id = visible_rows.read()[row - 1].0;
+                            if !expanded_nodes.read().contains(&id) {
+                                toggle_node(id);
                             }
                         }
+                        Key::Character(c) if c == "-" && row > 0 && tree_mode => {
+                            expanded_nodes.write().remove(&visible_rows.read()[row - 1].0);
+                        }
+                        Key::ContextMenu if row > 0 && has_row_actions => {
+                            context_menu.set(Some((visible_rows.read()[row - 1].0, row)));
+                        }
+                        Key::F10 if event.modifiers().shift() && row > 0
+                            && has_row_actions => {
+                            context_menu.set(Some((visible_rows.read()[row - 1].0, row)));
+                        }
+                        Key::Enter if row > 0 && col == 0 && props.row_details.is_some() => {
+                            toggle_expanded(visible_rows.read()[row - 1].0);
+                        }
+                        Key::Enter if row > 0 => {
+                            (props.ondetail)(visible_rows.read()[row - 1].0);
+                        }
+                        _ => return,
+                    }
+                    event.prevent_default();
+                },
+                div {
+                    class: "grid grid-cols-subgrid col-span-full",
+                    role: "row",
+                    "aria-rowindex": 1,
+                    div {
+                        class: "outline outline-gray-300 px-2 py-1 bg-gray-100 sticky left-0 z-[1]",
+                        id: "{scope}-cell-0-0",
+                        role: "columnheader",
+                        "aria-label": "Selection",
+                        tabindex: if focused_cell() == (0, 0) { 0 } else { -1 },
+                        onfocus: move |_| {
+                            focused_cell.set((0, 0));
+                            cell_has_focus.set(true);
+                        },
+                        onblur: move |_| cell_has_focus.set(false),
+                        onresize: move |event| {
+                            if let Ok(size) = event.get_border_box_size() {
+                                select_width.set(size.width);
+                            }
+                        },
+                    }
+                    for (i , (header , column)) in display_columns.iter().map(|c| (&c.name, c)).enumerate() {
                         div {
-                            // The anchor positioning polyfill requires inset-auto for whatever reason
-                            class: "border border-gray-300 rounded shadow-md p-2 absolute min-w-50 [position-anchor:filter-popover-{i}] [position-area:bottom_center] inset-auto",
-                            id: "filter-popover-{i}",
-                            popover: "auto",
-                            input {
-                                class: "border border-gray-300 rounded p-1 w-full",
-                                placeholder: "Filter by {header}",
-                                value: column_search_text()[idx].clone(),
-                                oninput: move |event: Event<FormData>| {
-                                    column_search_text
-                                        .with_mut(|vec| {
-                                            vec[idx] = event.value();
-                                        });
+                            key: "{header}",
+                            class: "outline outline-gray-300 bg-gray-100 flex min-w-0 relative",
+                            style: "anchor-name: --{scope}-header-{i+1}; {pin_styles().get(header).cloned().unwrap_or_default()}",
+                            id: "{scope}-cell-0-{i+1}",
+                            role: "columnheader",
+                            "aria-description": "Press Enter to sort, Alt+Left or Alt+Right to move the column",
+                            "aria-sort": if sort_column.as_ref() == Some(header) { if state.read().sort_ascending { "ascending" } else { "descending" } } else { "none" },
+                            tabindex: if focused_cell() == (0, i + 1) { 0 } else { -1 },
+                            onfocus: move |_| {
+                                focused_cell.set((0, i + 1));
+                                cell_has_focus.set(true);
+                            },
+                            onblur: move |_| cell_has_focus.set(false),
+                            onresize: {
+                                let header = header.clone();
+                                move |event: Event<ResizeData>| {
+                                    if let Ok(size) = event.get_border_box_size() {
+                                        measured_widths
+                                            .with_mut(|widths| {
+                                                widths.insert(header.clone(), size.width);
+                                            });
+                                    }
+                                }
+                            },
+                            // To make only the handle draggable, we need to set the draggable attribute conditionally
+                            // https://stackoverflow.com/questions/26283661/drag-drop-with-handle
+                            draggable: drag_state() == DragState::Mousedown(i),
+                            ondragstart: move |_| {
+                                if drag_state() == DragState::Mousedown(i) {
+                                    drag_state.set(DragState::Dragging(i));
+                                }
+                            },
+                            ondragend: move |_| {
+                                drag_state.set(DragState::None);
+                            },
+                            span { class: "font-bold px-2 py-1 truncate", "{header}" }
+                            // Sort button
+                            button {
+                                class: "ml-auto flex items-center px-1",
+                                tabindex: -1,
+                                "aria-label": "Sort by {header}",
+                                onclick: {
+                                    let header = header.clone();
+                                    move |_| state.write().toggle_sort(&columns.read(), &header)
                                 },
+                                svg {
+                                    class: if sort_column.as_ref() == Some(header) { if state.read().sort_ascending { "text-blue-500" } else { "text-blue-500 rotate-180" } } else { "" },
+                                    fill: "currentColor",
+                                    width: "24",
+                                    height: "24",
+                                    xmlns: "http://www.w3.org/2000/svg",
+                                    "viewBox": "0 -960 960 960",
+                                    path { d: "M480-528 296-344l-56-56 240-240 240 240-56 56z" }
+                                }
                             }
-                            // Checkboxes for categorical filters
-                            if columns().iter().find(|c| &c.name == header).unwrap().categorical {
-                                div { class: "mt-2",
-                                    for value in props
-                                        .data
-                                        .iter()
-                                        .filter_map(|row| {
-                                            row.get(columns().iter().position(|c| &c.name == header).unwrap())
-                                        })
-                                        .unique()
-                                        .sorted()
-                                    {
-                                        label {
-                                            key: "{value}",
-                                            class: "flex items-center gap-2",
-                                            input {
-                                                r#type: "checkbox",
-                                                checked: column_category_filter.get(idx).unwrap().contains(value),
-                                                onchange: {
-                                                    let value = value.clone();
-                                                    move |_| {
-                                                        column_category_filter
-                                                            .with_mut(|filters| {
-                                                                let filter = filters.get_mut(idx).unwrap();
-                                                                if filter.contains(&value) {
-                                                                    filter.remove(&value);
-                                                                } else {
-                                                                    filter.insert(value.clone());
-                                                                }
-                                                            });
-                                                    }
-                                                },
+                            // Filter button
+                            button {
+                                tabindex: -1,
+                                "aria-label": "Filter {header}",
+                                class: "flex items-center px-1",
+                                style: "anchor-name: --{scope}-filter-button-{i}",
+                                class: if state.read().is_filtered(header) { "text-blue-500" },
+                                popovertarget: "{scope}-filter-popover-{i}",
+                                svg {
+                                    fill: "currentColor",
+                                    "viewBox": "0 -960 960 960",
+                                    width: "24",
+                                    xmlns: "http://www.w3.org/2000/svg",
+                                    height: "24",
+                                    path { d: "M440-160q-17 0-28.5-11.5T400-200v-240L168-736q-15-20-4.5-42t36.5-22h560q26 0 36.5 22t-4.5 42L560-440v240q0 17-11.5 28.5T520-160zm40-308 198-252H282zm0 0" }
+                                }
+                            }
+                            div {
+                                // The anchor positioning polyfill requires inset-auto for whatever reason
+                                class: "border border-gray-300 rounded shadow-md p-2 absolute min-w-50 [position-area:bottom_center] inset-auto",
+                                style: "position-anchor: --{scope}-filter-button-{i}",
+                                id: "{scope}-filter-popover-{i}",
+                                popover: "auto",
+                                // Keep grid navigation from handling keys typed into the filter
+                                onkeydown: move |event| event.stop_propagation(),
+                                input {
+                                    class: "border border-gray-300 rounded p-1 w-full",
+                                    placeholder: "Filter by {header}",
+                                    value: state.read().column_search_text.get(header).cloned().unwrap_or_default(),
+                                    oninput: {
+                                        let header = header.clone();
+                                        move |event: Event<FormData>| {
+                                            state.write().set_column_search_text(&header, event.value());
+                                        }
+                                    },
+                                }
+                                // Checkboxes for categorical filters
+                                if column.categorical {
+                                    div { class: "mt-2",
+                                        for value in props
+                                            .data
+                                            .read()
+                                            .iter()
+                                            .filter_map(|row| {
+                                                row.get(columns().iter().position(|c| &c.name == header)?)
+                                            })
+                                            .flat_map(|cell| column.values(cell))
+                                            .map(str::to_string)
+                                            .unique()
+                                            .sorted_by(|a, b| column.compare(a, b))
+                                        {
+                                            label {
+                                                key: "{value}",
+                                                class: "flex items-center gap-2",
+                                                input {
+                                                    r#type: "checkbox",
+                                                    checked: state.read().column_category_filter.get(header).is_some_and(|set| set.contains(&value)),
+                                                    onchange: {
+                                                        let header = header.clone();
+                                                        let value = value.clone();
+                                                        move |_| state.write().toggle_category(&header, &value)
+                                                    },
+                                                }
+                                                span { "{value}" }
                                             }
-                                            span { "{value}" }
                                         }
                                     }
                                 }
+                                // Reset filter button
+                                button {
+                                    class: "border border-gray-300 rounded px-2 py-1 mt-2 bg-gray-100 hover:bg-gray-200 text-sm",
+                                    onclick: {
+                                        let header = header.clone();
+                                        move |_| state.write().reset_filter(&header)
+                                    },
+                                    "Reset Filter"
+                                }
                             }
-                            // Reset filter button
-                            button {
-                                class: "border border-gray-300 rounded px-2 py-1 mt-2 bg-gray-100 hover:bg-gray-200 text-sm",
-                                onclick: move |_| {
-                                    column_search_text
-                                        .with_mut(|vec| {
-                                            vec[idx] = String::new();
-                                        });
-                                    column_category_filter
-                                        .with_mut(|vec| {
-                                            vec[idx] = HashSet::new();
-                                        });
+                            // Drag handle
+                            div {
+                                class: "flex items-center px-1 cursor-grab",
+                                "aria-hidden": "true",
+                                onmousedown: move |_| {
+                                    drag_state.set(DragState::Mousedown(i));
+                                },
+                                onmouseup: move |_| {
+                                    drag_state.set(DragState::None);
+                                },
+                                svg {
+                                    "viewBox": "0 -960 960 960",
+                                    width: "24",
+                                    xmlns: "http://www.w3.org/2000/svg",
+                                    height: "24",
+                                    path { d: "M360-160q-33 0-56.5-23.5T280-240t23.5-56.5T360-320t56.5 23.5T440-240t-23.5 56.5T360-160m240 0q-33 0-56.5-23.5T520-240t23.5-56.5T600-320t56.5 23.5T680-240t-23.5 56.5T600-160M360-400q-33 0-56.5-23.5T280-480t23.5-56.5T360-560t56.5 23.5T440-480t-23.5 56.5T360-400m240 0q-33 0-56.5-23.5T520-480t23.5-56.5T600-560t56.5 23.5T680-480t-23.5 56.5T600-400M360-640q-33 0-56.5-23.5T280-720t23.5-56.5T360-800t56.5 23.5T440-720t-23.5 56.5T360-640m240 0q-33 0-56.5-23.5T520-720t23.5-56.5T600-800t56.5 23.5T680-720t-23.5 56.5T600-640" }
+                                }
+                            }
+                            // Resize handle, double-click to fit the column to its content
+                            div {
+                                class: "absolute top-0 -right-px h-full w-1 cursor-col-resize hover:bg-blue-500",
+                                onmousedown: {
+                                    let header = header.clone();
+                                    move |event: Event<MouseData>| {
+                                        event.stop_propagation();
+                                        resize_state
+                                            .set(
+                                                Some(ResizeState {
+                                                    column: header.clone(),
+                                                    start_x: event.client_coordinates().x,
+                                                    start_width: measured_widths().get(&header).copied().unwrap_or_default(),
+                                                }),
+                                            );
+                                    }
+                                },
+                                ondoubleclick: {
+                                    let header = header.clone();
+                                    move |_| {
+                                        state.write().column_widths.insert(header.clone(), ColumnWidth::FitContent);
+                                    }
                                 },
-                                "Reset Filter"
                             }
                         }
-                        // Drag handle
+                    }
+                    div {
+                        class: "outline outline-gray-300 px-2 py-1 bg-gray-100 sticky right-0 z-[1]",
+                        id: "{scope}-cell-0-{state.read().columns.len() + 1}",
+                        role: "columnheader",
+                        "aria-label": "Details",
+                        tabindex: if focused_cell() == (0, state.read().columns.len() + 1) { 0 } else { -1 },
+                        onfocus: move |_| {
+                            focused_cell.set((0, state.read().columns.len() + 1));
+                            cell_has_focus.set(true);
+                        },
+                        onblur: move |_| cell_has_focus.set(false),
+                        onresize: move |event| {
+                            if let Ok(size) = event.get_border_box_size() {
+                                detail_width.set(size.width);
+                            }
+                        },
+                    }
+                    // Dragover indicator
+                    if let DragState::Dragover(_, i) = drag_state() {
                         div {
-                            class: "flex items-center px-1 cursor-grab",
-                            onmousedown: move |_| {
-                                drag_state.set(DragState::Mousedown(i));
-                            },
-                            onmouseup: move |_| {
-                                drag_state.set(DragState::None);
-                            },
-                            svg {
-                                "viewBox": "0 -960 960 960",
-                                width: "24",
-                                xmlns: "http://www.w3.org/2000/svg",
-                                height: "24",
-                                path { d: "M360-160q-33 0-56.5-23.5T280-240t23.5-56.5T360-320t56.5 23.5T440-240t-23.5 56.5T360-160m240 0q-33 0-56.5-23.5T520-240t23.5-56.5T600-320t56.5 23.5T680-240t-23.5 56.5T600-160M360-400q-33 0-56.5-23.5T280-480t23.5-56.5T360-560t56.5 23.5T440-480t-23.5 56.5T360-400m240 0q-33 0-56.5-23.5T520-480t23.5-56.5T600-560t56.5 23.5T680-480t-23.5 56.5T600-400M360-640q-33 0-56.5-23.5T280-720t23.5-56.5T360-800t56.5 23.5T440-720t-23.5 56.5T360-640m240 0q-33 0-56.5-23.5T520-720t23.5-56.5T600-800t56.5 23.5T680-720t-23.5 56.5T600-640" }
+                            class: "absolute bg-blue-500 w-[3px]",
+                            style: "top: anchor(--{scope}-header-1 top); bottom: anchor(--{scope}-header-1 bottom);",
+                            style: if i == 0 { "left: anchor(--{scope}-header-1 left); translate: -2px;" } else { "right: anchor(--{scope}-header-{i} right); translate: 2px;" },
+                        }
+                    }
+                    // Create invisible drop zones between headers
+                    match drag_state() {
+                        DragState::Dragging(dragged_index) | DragState::Dragover(dragged_index, _) => {
+                            rsx! {
+                                for i in 0..state.read().columns.len() + 1 {
+                                    if i != dragged_index && i != dragged_index + 1 {
+                                        div {
+                                            class: "absolute",
+                                            style: "left: anchor(--{scope}-header-{i} center, 0); right: anchor(--{scope}-header-{i+1} center, 0); top: anchor(--{scope}-header-1 -50%); bottom: anchor(--{scope}-header-1 150%);",
+                                            ondragover: move |event| {
+                                                event.prevent_default();
+                                                if drag_state() != DragState::Dragover(dragged_index, i) {
+                                                    drag_state.set(DragState::Dragover(dragged_index, i));
+                                                }
+                                            },
+                                            ondragleave: move |_| {
+                                                drag_state.set(DragState::Dragging(dragged_index));
+                                            },
+                                            ondrop: move |_| {
+                                                let insert_at = if i > dragged_index { i - 1 } else { i };
+                                                state.write().move_column(dragged_index, insert_at);
+                                            },
+                                        }
+                                    }
+                                }
                             }
                         }
+                        _ => rsx! {},
                     }
                 }
-                div { class: "outline outline-gray-300 px-2 py-1 bg-gray-100" }
-                // Dragover indicator
-                if let DragState::Dragover(_, i) = drag_state() {
-                    div {
-                        class: "absolute bg-blue-500 w-[3px]",
-                        style: "top: anchor(--header-1 top); bottom: anchor(--header-1 bottom);",
-                        style: if i == 0 { "left: anchor(--header-1 left); translate: -2px;" } else { "right: anchor(--header-{i} right); translate: 2px;" },
+                for (group , start) in groups_with_start {
+                    if state.read().group_by.is_some() {
+                        div {
+                            key: "group-{group.key}",
+                            class: "col-span-full outline outline-gray-300 px-2 py-1 bg-gray-50 flex items-center gap-4",
+                            role: "row",
+                            div { class: "flex items-center gap-2", role: "gridcell",
+                                button {
+                                    class: "flex items-center",
+                                    "aria-expanded": !group.collapsed,
+                                    "aria-label": if group.collapsed { "Expand group" } else { "Collapse group" },
+                                    onclick: {
+                                        let key = group.key.clone();
+                                        move |_| state.write().toggle_group(&key)
+                                    },
+                                    svg {
+                                        class: if group.collapsed { "size-5" } else { "size-5 rotate-90" },
+                                        "viewBox": "0 0 24 24",
+                                        "stroke-width": "1.5",
+                                        xmlns: "http://www.w3.org/2000/svg",
+                                        stroke: "currentColor",
+                                        fill: "none",
+                                        path {
+                                            "stroke-linejoin": "round",
+                                            d: "m8.25 4.5 7.5 7.5-7.5 7.5",
+                                            "stroke-linecap": "round",
+                                        }
+                                    }
+                                }
+                                span { class: "font-bold", "{group.label}" }
+                                span { class: "text-gray-500", "({group.rows.len()})" }
+                            }
+                            for aggregate in group.aggregates.iter() {
+                                span { class: "text-sm text-gray-500", "{aggregate}" }
+                            }
+                        }
                     }
-                }
-                // Create invisible drop zones between headers
-                match drag_state() {
-                    DragState::Dragging(dragged_index) | DragState::Dragover(dragged_index, _) => {
-                        rsx! {
-                            for i in 0..custom_columns().len() + 1 {
-                                if i != dragged_index && i != dragged_index + 1 {
-                                    div {
-                                        class: "absolute",
-                                        style: "left: anchor(--header-{i} center, 0); right: anchor(--header-{i+1} center, 0); top: anchor(--header-1 -50%); bottom: anchor(--header-1 150%);",
-                                        ondragover: move |event| {
-                                            event.prevent_default();
-                                            if drag_state() != DragState::Dragover(dragged_index, i) {
-                                                drag_state.set(DragState::Dragover(dragged_index, i));
+                    if !group.collapsed {
+                        for (r , (id , row)) in (start..).zip(group.rows) {
+                            div {
+                                class: "grid grid-cols-subgrid col-span-full",
+                                role: "row",
+                                "aria-rowindex": r + 1,
+                                "aria-selected": selected_rows().contains(&id),
+                                "aria-level": tree_mode.then(|| depth(id) + 1),
+                                style: if context_menu().is_some_and(|(menu_row, _)| menu_row == id) { "anchor-name: --{scope}-context-row" },
+                                oncontextmenu: move |event| {
+                                    if has_row_actions {
+                                        event.prevent_default();
+                                        context_menu.set(Some((id, r)));
+                                    }
+                                },
+                                "aria-expanded": props.tree.read().get(id).filter(|node| node.expandable).map(|_| expanded_nodes().contains(&id)),
+                                draggable: props.draggable_rows,
+                                ondragstart: move |_| {
+                                    // Dragging a selected row drags the whole selection
+                                    let rows = if selected_rows.read().contains(&id) {
+                                        selected_rows.read().iter().copied().sorted().collect()
+                                    } else {
+                                        vec![id]
+                                    };
+                                    let data = props.data.read();
+                                    *DRAGGED_ROWS.write() = Some(RowsDrop {
+                                        source: scope(),
+                                        values: rows.iter().map(|&row| data[row].clone()).collect(),
+                                        rows,
+                                    });
+                                },
+                                ondragend: move |_| *DRAGGED_ROWS.write() = None,
+                                label {
+                                    class: "outline outline-gray-300 px-2 py-1 flex items-center bg-white sticky left-0 z-[1]",
+                                    id: "{scope}-cell-{r}-0",
+                                    role: "gridcell",
+                                    tabindex: if focused_cell() == (r, 0) { 0 } else { -1 },
+                                    onfocus: move |_| {
+                                        focused_cell.set((r, 0));
+                                        cell_has_focus.set(true);
+                                    },
+                                    onblur: move |_| cell_has_focus.set(false),
+                                    input {
+                                        r#type: "checkbox",
+                                        tabindex: -1,
+                                        "aria-label": "Select row",
+                                        checked: selected_rows().contains(&id),
+                                        onchange: move |_| toggle_selected(id),
+                                    }
+                                    if props.row_details.is_some() {
+                                        button {
+                                            class: "ml-1 flex items-center",
+                                            tabindex: -1,
+                                            "aria-expanded": expanded_rows().contains(&id),
+                                            "aria-controls": "{scope}-details-{id}",
+                                            "aria-label": if expanded_rows().contains(&id) { "Hide row details" } else { "Show row details" },
+                                            onclick: move |event| {
+                                                // Don't toggle the checkbox of the surrounding label
+                                                event.prevent_default();
+                                                toggle_expanded(id);
+                                            },
+                                            svg {
+                                                class: if expanded_rows().contains(&id) { "size-5 rotate-90" } else { "size-5" },
+                                                "viewBox": "0 0 24 24",
+                                                "stroke-width": "1.5",
+                                                xmlns: "http://www.w3.org/2000/svg",
+                                                stroke: "currentColor",
+                                                fill: "none",
+                                                path {
+                                                    "stroke-linejoin": "round",
+                                                    d: "m8.25 4.5 7.5 7.5-7.5 7.5",
+                                                    "stroke-linecap": "round",
+                                                }
                                             }
+                                        }
+                                    }
+                                }
+                                for (c , (cell , column)) in (1..).zip(row.iter().zip(display_columns.iter())) {
+                                    div {
+                                        class: "outline outline-gray-300 px-2 py-1 bg-white min-w-0 overflow-hidden text-ellipsis",
+                                        class: if tree_mode && c == 1 { "flex items-center gap-1" },
+                                        style: cell_style(&column.name, id, c),
+                                        id: "{scope}-cell-{r}-{c}",
+                                        role: "gridcell",
+                                        tabindex: if focused_cell() == (r, c) { 0 } else { -1 },
+                                        onfocus: move |_| {
+                                            focused_cell.set((r, c));
+                                            cell_has_focus.set(true);
                                         },
-                                        ondragleave: move |_| {
-                                            drag_state.set(DragState::Dragging(dragged_index));
-                                        },
-                                        ondrop: move |_| {
-                                            custom_columns
-                                                .with_mut(|cols| {
-                                                    let col = cols.remove(dragged_index);
-                                                    let insert_at = if i > dragged_index { i - 1 } else { i };
-                                                    cols.insert(insert_at, col);
-                                                });
+                                        onblur: move |_| cell_has_focus.set(false),
+                                        ondoubleclick: {
+                                            let column = column.clone();
+                                            let cell = cell.clone();
+                                            move |_| start_edit(id, &column, &cell)
                                         },
+                                        if tree_mode && c == 1 {
+                                            if props.tree.read().get(id).is_some_and(|node| node.expandable) {
+                                                button {
+                                                    class: "flex items-center shrink-0",
+                                                    tabindex: -1,
+                                                    "aria-label": if expanded_nodes().contains(&id) { "Collapse" } else { "Expand" },
+                                                    onclick: move |_| toggle_node(id),
+                                                    svg {
+                                                        class: if expanded_nodes().contains(&id) { "size-5 rotate-90" } else { "size-5" },
+                                                        "viewBox": "0 0 24 24",
+                                                        "stroke-width": "1.5",
+                                                        xmlns: "http://www.w3.org/2000/svg",
+                                                        stroke: "currentColor",
+                                                        fill: "none",
+                                                        path {
+                                                            "stroke-linejoin": "round",
+                                                            d: "m8.25 4.5 7.5 7.5-7.5 7.5",
+                                                            "stroke-linecap": "round",
+                                                        }
+                                                    }
+                                                }
+                                            } else {
+                                                span { class: "size-5 shrink-0" }
+                                            }
+                                        }
+                                        if let Some(edit) = editing().filter(|e| e.row == id && e.column == column.name) {
+                                            input {
+                                                class: "w-full border border-blue-500 rounded px-1",
+                                                "aria-label": "Edit {column.name}",
+                                                "aria-invalid": edit.error.is_some(),
+                                                value: "{edit.draft}",
+                                                onmounted: move |event| async move {
+                                                    let _ = event.set_focus(true).await;
+                                                },
+                                                oninput: move |event| {
+                                                    editing
+                                                        .with_mut(|edit| {
+                                                            if let Some(edit) = edit {
+                                                                edit.draft = event.value();
+                                                                edit.error = None;
+                                                            }
+                                                        });
+                                                },
+                                                onkeydown: move |event| {
+                                                    // Keep grid navigation from handling keys typed into the input
+                                                    event.stop_propagation();
+                                                    match event.key() {
+                                                        Key::Enter => {
+                                                            commit_edit();
+                                                            if editing().is_none() {
+                                                                focus_cell(r, c);
+                                                            }
+                                                        }
+                                                        Key::Escape => {
+                                                            editing.set(None);
+                                                            focus_cell(r, c);
+                                                        }
+                                                        _ => {}
+                                                    }
+                                                },
+                                                onblur: move |_| commit_edit(),
+                                            }
+                                            if let Some(error) = &edit.error {
+                                                div { class: "text-red-600 text-sm", role: "alert", "{error}" }
+                                            }
+                                        } else if let Some(render) = column.render {
+                                            {
+                                                render
+                                                    .call(Cell {
+                                                        row: id,
+                                                        value: cell.clone(),
+                                                        highlights: state.read().highlights(cell),
+                                                    })
+                                            }
+                                        } else if let ColumnKind::Text | ColumnKind::Number = column.kind {
+                                            Highlight { text: cell.clone(), positions: state.read().highlights(cell) }
+                                        } else {
+                                            "{column.kind.display(cell)}"
+                                        }
+                                        if tree_mode && c == 1 && loading_nodes().contains(&id) {
+                                            span { class: "text-sm text-gray-500", "Loading..." }
+                                        }
+                                    }
+                                }
+                                button {
+                                    class: "outline outline-gray-300 px-2 py-1 bg-white sticky right-0 z-[1]",
+                                    id: "{scope}-cell-{r}-{row.len() + 1}",
+                                    role: "gridcell",
+                                    "aria-label": "Show details",
+                                    tabindex: if focused_cell() == (r, row.len() + 1) { 0 } else { -1 },
+                                    onfocus: move |_| {
+                                        focused_cell.set((r, state.read().columns.len() + 1));
+                                        cell_has_focus.set(true);
+                                    },
+                                    onblur: move |_| cell_has_focus.set(false),
+                                    onclick: move |_| {
+                                        (props.ondetail)(id);
+                                    },
+                                    svg {
+                                        "viewBox": "0 0 24 24",
+                                        "stroke-width": "1.5",
+                                        xmlns: "http://www.w3.org/2000/svg",
+                                        stroke: "currentColor",
+                                        fill: "none",
+                                        class: "size-6",
+                                        path {
+                                            "stroke-linejoin": "round",
+                                            d: "m8.25 4.5 7.5 7.5-7.5 7.5",
+                                            "stroke-linecap": "round",
+                                        }
                                     }
                                 }
                             }
+                            if let Some(row_details) = props.row_details.filter(|_| expanded_rows().contains(&id)) {
+                                div {
+                                    class: "col-span-full outline outline-gray-300 px-4 py-2 bg-gray-50",
+                                    id: "{scope}-details-{id}",
+                                    role: "row",
+                                    div { role: "gridcell", {row_details.call(id)} }
+                                }
+                            }
                         }
                     }
-                    _ => rsx! {},
                 }
-            }
-            for (id , row) in filtered_data().into_iter() {
-                div { class: "grid grid-cols-subgrid col-span-full",
-                    label { class: "outline outline-gray-300 px-2 py-1 flex items-center",
-                        input { r#type: "checkbox" }
-                    }
-                    for cell in row.iter() {
-                        div { class: "outline outline-gray-300 px-2 py-1", "{cell}" }
-                    }
-                    button {
-                        class: "outline outline-gray-300 px-2 py-1",
-                        onclick: move |_| {
-                            (props.ondetail)(id);
-                        },
-                        svg {
-                            "viewBox": "0 0 24 24",
-                            "stroke-width": "1.5",
-                            xmlns: "http://www.w3.org/2000/svg",
-                            stroke: "currentColor",
-                            fill: "none",
-                            class: "size-6",
-                            path {
-                                "stroke-linejoin": "round",
-                                d: "m8.25 4.5 7.5 7.5-7.5 7.5",
-                                "stroke-linecap": "round",
+                if props.footer {
+                    div {
+                        class: "grid grid-cols-subgrid col-span-full",
+                        role: "row",
+                        div { class: "outline outline-gray-300 px-2 py-1 bg-gray-100 sticky left-0 z-[1]" }
+                        for (i , (Column { name: header , kind , categorical , .. } , (aggregates , (distinct , top_values)))) in display_columns
+                            .iter()
+                            .zip(footer_stats)
+                            .enumerate()
+                        {
+                            div {
+                                key: "{header}",
+                                class: "outline outline-gray-300 px-2 py-1 bg-gray-100 text-sm flex items-start gap-2",
+                                style: pin_styles().get(header).cloned(),
+                                role: "gridcell",
+                                div { class: "grow",
+                                    for (label , value) in aggregates {
+                                        div { key: "{label}",
+                                            span { class: "text-gray-500", "{label}: " }
+                                            "{value}"
+                                        }
+                                    }
+                                }
+                                // Column statistics button
+                                button {
+                                    class: "flex items-center",
+                                    style: "anchor-name: --{scope}-stats-button-{i}",
+                                    "aria-label": "Statistics for {header}",
+                                    popovertarget: "{scope}-stats-popover-{i}",
+                                    svg {
+                                        fill: "currentColor",
+                                        "viewBox": "0 -960 960 960",
+                                        width: "20",
+                                        xmlns: "http://www.w3.org/2000/svg",
+                                        height: "20",
+                                        path { d: "M640-160v-280h160v280zm-240 0v-640h160v640zm-240 0v-440h160v440z" }
+                                    }
+                                }
+                                div {
+                                    // The anchor positioning polyfill requires inset-auto for whatever reason
+                                    class: "border border-gray-300 rounded shadow-md p-2 absolute min-w-50 [position-area:top_center] inset-auto",
+                                    style: "position-anchor: --{scope}-stats-button-{i}",
+                                    id: "{scope}-stats-popover-{i}",
+                                    popover: "auto",
+                                    div { class: "font-bold", "{header}" }
+                                    div { "{distinct} distinct values" }
+                                    if *categorical {
+                                        div { class: "mt-2 text-gray-500", "Top values" }
+                                        for (value , count) in top_values {
+                                            div { key: "{value}", class: "flex justify-between gap-4",
+                                                span { "{kind.display(&value)}" }
+                                                span { class: "text-gray-500", "{count}" }
+                                            }
+                                        }
+                                    }
+                                }
                             }
                         }
+                        div { class: "outline outline-gray-300 px-2 py-1 bg-gray-100 sticky right-0 z-[1]" }
                     }
                 }
             }
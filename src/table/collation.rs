@@ -0,0 +1,221 @@
+//! Ordering of text cells
+
+use std::cmp::Ordering;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// How the cells of a text column are compared when sorting and grouping
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Collation {
+    /// Plain string order by code point, so uppercase sorts before lowercase and umlauts at the end
+    Binary,
+    /// Case-insensitive and ignoring accents as in German dictionaries, so "Äpfel" sorts with "apfel"
+    /// and "Straße" with "strasse"
+    Locale,
+    /// Like `Locale`, but runs of digits compare by their value, so "file2" sorts before "file10".
+    /// Suited for file names.
+    #[default]
+    Natural,
+}
+
+impl Collation {
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        self.key(a).cmp(&self.key(b))
+    }
+
+    /// The text prepared for comparing, so it is only folded once when sorting many strings
+    pub fn key(self, s: &str) -> CollationKey {
+        CollationKey {
+            collation: self,
+            folded: match self {
+                Collation::Binary => String::new(),
+                Collation::Locale | Collation::Natural => fold(s),
+            },
+            text: s.to_string(),
+        }
+    }
+}
+
+/// A string ordered by a collation. Strings folding to the same text are ordered by code point, so
+/// the order is total.
+#[derive(Debug)]
+pub struct CollationKey {
+    collation: Collation,
+    folded: String,
+    text: String,
+}
+
+impl Ord for CollationKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.collation {
+            Collation::Binary => Ordering::Equal,
+            Collation::Locale => self.folded.cmp(&other.folded),
+            Collation::Natural => natural_cmp(&self.folded, &other.folded),
+        }
+        .then_with(|| self.text.cmp(&other.text))
+    }
+}
+
+impl PartialOrd for CollationKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CollationKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CollationKey {}
+
+/// Lowercase, remove accents and expand ligatures, so strings differing only in these compare equal
+fn fold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'æ' | 'Æ' => folded.push_str("ae"),
+            'œ' | 'Œ' => folded.push_str("oe"),
+            _ => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// Compare strings chunk by chunk, comparing runs of ASCII digits by their numeric value. Numbers
+/// with more leading zeros sort after equal numbers with fewer.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = Chunks(a);
+    let mut b = Chunks(b);
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ordering = match (is_number(x), is_number(y)) {
+                    (true, true) => {
+                        let x_digits = x.trim_start_matches('0');
+                        let y_digits = y.trim_start_matches('0');
+                        // Without leading zeros, a longer number is larger
+                        x_digits
+                            .len()
+                            .cmp(&y_digits.len())
+                            .then_with(|| x_digits.cmp(y_digits))
+                            .then_with(|| x.len().cmp(&y.len()))
+                    }
+                    _ => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+fn is_number(chunk: &str) -> bool {
+    chunk.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Splits a string into alternating runs of digits and non-digits
+struct Chunks<'a>(&'a str);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let digits = is_number(self.0);
+        let end = self
+            .0
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(self.0.len());
+        if end == 0 {
+            return None;
+        }
+        let (chunk, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(collation: Collation, strings: &[&str]) -> Vec<String> {
+        let mut strings = strings.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        strings.sort_by(|a, b| collation.compare(a, b));
+        strings
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(
+            sorted(Collation::Natural, &["file10", "file2", "file1", "file"]),
+            ["file", "file1", "file2", "file10"]
+        );
+        assert_eq!(
+            sorted(Collation::Binary, &["file10", "file2"]),
+            ["file10", "file2"]
+        );
+    }
+
+    #[test]
+    fn leading_zeros_break_ties() {
+        assert_eq!(Collation::Natural.compare("007", "7"), Ordering::Greater);
+        assert_eq!(Collation::Natural.compare("07", "007"), Ordering::Less);
+        assert_eq!(Collation::Natural.compare("007", "8"), Ordering::Less);
+        assert_eq!(Collation::Natural.compare("007", "007"), Ordering::Equal);
+        assert_eq!(
+            sorted(Collation::Natural, &["a007", "a7", "a07", "a6"]),
+            ["a6", "a7", "a07", "a007"]
+        );
+    }
+
+    #[test]
+    fn digit_and_text_chunks_alternate() {
+        assert_eq!(
+            sorted(
+                Collation::Natural,
+                &["img12b3", "img12b10", "img12a", "img2", "12img", "img"]
+            ),
+            ["12img", "img", "img2", "img12a", "img12b3", "img12b10"]
+        );
+        // Runs too long for any integer type still compare by value
+        assert_eq!(
+            Collation::Natural.compare("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn accents_and_ligatures_fold() {
+        let words = [
+            "Zebra", "apfel", "Äpfel", "Apfel", "Straße", "strasse", "Æsop", "aesop",
+        ];
+        let expected = [
+            "aesop", "Æsop", "Apfel", "apfel", "Äpfel", "Straße", "strasse", "Zebra",
+        ];
+        assert_eq!(sorted(Collation::Locale, &words), expected);
+        assert_eq!(sorted(Collation::Natural, &words), expected);
+        assert_eq!(
+            sorted(Collation::Binary, &["Zebra", "apfel", "Äpfel"]),
+            ["Zebra", "apfel", "Äpfel"]
+        );
+    }
+
+    #[test]
+    fn case_is_ignored_before_ties() {
+        assert_eq!(
+            sorted(Collation::Locale, &["b", "B", "a", "C"]),
+            ["a", "B", "b", "C"]
+        );
+        assert_eq!(Collation::Locale.compare("ß", "ss"), Ordering::Greater);
+        assert_eq!(Collation::Locale.compare("ss", "st"), Ordering::Less);
+        assert_eq!(Collation::Locale.compare("ß", "st"), Ordering::Less);
+    }
+}
@@ -0,0 +1,1140 @@
+//! Filtering, sorting, projection and column layout of a table, independent of how it is rendered
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use itertools::Itertools;
+
+use super::collation::CollationKey;
+use super::{Column, ColumnWidth, Pin, TreeNode, View, fuzzy};
+
+/// How the cells of a column are interpreted for display, sorting and aggregation
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColumnKind {
+    Text,
+    /// Cells are decimal numbers
+    Number,
+    /// Cells are a number of bytes
+    Bytes,
+    /// Cells are an RFC 3339 timestamp
+    Date,
+}
+
+impl ColumnKind {
+    pub fn compare(self, a: &str, b: &str) -> std::cmp::Ordering {
+        match self {
+            ColumnKind::Text => a.cmp(b),
+            // Cells that aren't finite numbers come first
+            ColumnKind::Number => match (parse_number(a), parse_number(b)) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            },
+            ColumnKind::Bytes => a.parse::<u64>().ok().cmp(&b.parse::<u64>().ok()),
+            ColumnKind::Date => parse_date(a).cmp(&parse_date(b)),
+        }
+    }
+
+    pub fn display(self, cell: &str) -> String {
+        match self {
+            ColumnKind::Text | ColumnKind::Number => cell.to_string(),
+            ColumnKind::Bytes => cell
+                .parse()
+                .map(crate::format_bytes)
+                .unwrap_or_else(|_| cell.to_string()),
+            ColumnKind::Date => match (cell.parse(), cell.parse()) {
+                (Ok(timestamp), _) => crate::format_timestamp(timestamp),
+                (_, Ok(date)) => crate::format_date(date),
+                _ => cell.to_string(),
+            },
+        }
+    }
+
+    /// The value rows are grouped by. Dates are grouped by day.
+    pub fn group_key(self, cell: &str) -> String {
+        match self {
+            ColumnKind::Date => cell
+                .parse::<jiff::Timestamp>()
+                .map(|t| t.to_zoned(jiff::tz::TimeZone::system()).date())
+                .or_else(|_| cell.parse::<jiff::civil::Date>())
+                .map(|date| date.to_string())
+                .unwrap_or_else(|_| cell.to_string()),
+            _ => cell.to_string(),
+        }
+    }
+}
+
+/// Value of a number cell. "NaN" and infinities parse as floats but aren't numbers of a column, and
+/// would break sorting and totals.
+fn parse_number(cell: &str) -> Option<f64> {
+    cell.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Point in time of a date cell, which holds either a timestamp or a calendar date. Calendar dates
+/// are taken as the start of the day in the system time zone.
+fn parse_date(cell: &str) -> Option<jiff::Timestamp> {
+    cell.parse().ok().or_else(|| {
+        let date = cell.parse::<jiff::civil::Date>().ok()?;
+        Some(
+            date.to_zoned(jiff::tz::TimeZone::system())
+                .ok()?
+                .timestamp(),
+        )
+    })
+}
+
+impl Column {
+    /// The values of a cell: the comma-separated values for list columns, otherwise the cell itself
+    pub fn values<'a>(&self, cell: &'a str) -> Vec<&'a str> {
+        if self.list {
+            cell.split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .collect()
+        } else {
+            vec![cell]
+        }
+    }
+
+    /// Compare two cells of this column, using its collation for text
+    pub fn compare(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        match self.kind {
+            ColumnKind::Text => self.collation.compare(a, b),
+            kind => kind.compare(a, b),
+        }
+    }
+
+    /// A cell prepared for sorting, ordered like `compare`
+    fn sort_key(&self, cell: &str) -> SortKey {
+        match self.kind {
+            ColumnKind::Text => SortKey::Text(self.collation.key(cell)),
+            kind => SortKey::Cell(kind, cell.to_string()),
+        }
+    }
+}
+
+/// The cell a row is sorted by, with text folded once per row instead of on every comparison
+enum SortKey {
+    Text(CollationKey),
+    Cell(ColumnKind, String),
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            (SortKey::Cell(kind, a), SortKey::Cell(_, b)) => kind.compare(a, b),
+            // Only the keys of a single column are compared
+            _ => std::cmp::Ordering::Equal,
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+/// A row by its index in the data, with edits applied
+pub type Row<'a> = (usize, Cow<'a, [String]>);
+
+/// The rows of the data with the edited cells replaced, by row and column index
+pub fn apply_edits<'a>(
+    data: &'a [Vec<String>],
+    edits: &'a HashMap<usize, HashMap<usize, String>>,
+) -> impl Iterator<Item = Row<'a>> {
+    data.iter()
+        .enumerate()
+        .map(|(id, row)| match edits.get(&id) {
+            Some(edits) => (
+                id,
+                Cow::Owned(
+                    row.iter()
+                        .enumerate()
+                        .map(|(i, cell)| edits.get(&i).unwrap_or(cell).clone())
+                        .collect(),
+                ),
+            ),
+            None => (id, Cow::Borrowed(row.as_slice())),
+        })
+}
+
+/// Rows sharing the same value in the grouped-by column
+#[derive(Clone, PartialEq, Debug)]
+pub struct Group {
+    pub key: String,
+    pub label: String,
+    pub rows: Vec<(usize, Vec<String>)>,
+    /// Summaries of the rows in this group, e.g. "Size: 1.2 MB total"
+    pub aggregates: Vec<String>,
+    pub collapsed: bool,
+}
+
+impl Group {
+    /// A single unnamed group of all rows, used when rows aren't grouped
+    pub fn ungrouped(rows: Vec<(usize, Vec<String>)>) -> Self {
+        Self {
+            key: String::new(),
+            label: String::new(),
+            rows,
+            aggregates: Vec::new(),
+            collapsed: false,
+        }
+    }
+}
+
+/// The user-controlled state of a table: which columns are shown in which order, how rows are
+/// filtered, sorted and grouped. All operations are plain functions of this state and the data,
+/// so the same rows are produced wherever the state is used.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TableState {
+    /// Visible columns in display order
+    pub columns: Vec<String>,
+    /// Column explicitly chosen for sorting
+    pub sort_by: Option<String>,
+    pub sort_ascending: bool,
+    pub search_text: String,
+    /// Match the search text approximately and rank rows by how well they match
+    pub fuzzy_search: bool,
+    /// Column-specific filter text by column name
+    pub column_search_text: BTreeMap<String, String>,
+    /// Selected categories by column name
+    pub column_category_filter: BTreeMap<String, BTreeSet<String>>,
+    pub column_widths: BTreeMap<String, ColumnWidth>,
+    pub pinned_columns: BTreeMap<String, Pin>,
+    pub group_by: Option<String>,
+    /// Keys of the groups whose rows are hidden
+    pub collapsed_groups: HashSet<String>,
+}
+
+impl TableState {
+    /// Default state: all columns not hidden by default in their given order, sorted by the first column
+    pub fn new(columns: &[Column]) -> Self {
+        Self {
+            columns: default_columns(columns),
+            sort_by: None,
+            sort_ascending: true,
+            search_text: String::new(),
+            fuzzy_search: false,
+            column_search_text: BTreeMap::new(),
+            column_category_filter: BTreeMap::new(),
+            column_widths: BTreeMap::new(),
+            pinned_columns: BTreeMap::new(),
+            group_by: None,
+            collapsed_groups: HashSet::new(),
+        }
+    }
+
+    /// Restore the state saved in a view, ignoring columns that no longer exist
+    pub fn apply_view(&mut self, columns: &[Column], view: &View) {
+        let exists = |name: &String| columns.iter().any(|c| &c.name == name);
+        self.columns = view.columns.iter().filter(|c| exists(c)).cloned().collect();
+        self.pinned_columns = view.pinned_columns.clone();
+        order_pinned(&mut self.columns, &self.pinned_columns);
+        self.column_widths = view.column_widths.clone();
+        self.group_by = view.group_by.clone().filter(exists);
+        self.collapsed_groups = HashSet::new();
+        self.sort_by = Some(view.sort_by.clone()).filter(exists);
+        self.sort_ascending = view.sort_ascending;
+        self.search_text = view.search_text.clone();
+        self.fuzzy_search = view.fuzzy_search;
+        self.column_search_text = view
+            .column_search_text
+            .iter()
+            .filter(|(name, text)| exists(name) && !text.is_empty())
+            .map(|(name, text)| (name.clone(), text.clone()))
+            .collect();
+        self.column_category_filter = view
+            .column_category_filter
+            .iter()
+            .filter(|(name, set)| exists(name) && !set.is_empty())
+            .map(|(name, set)| (name.clone(), set.clone()))
+            .collect();
+    }
+
+    /// Whether the state refers only to the given columns
+    pub fn has_only(&self, columns: &[Column]) -> bool {
+        let exists = |name: &String| columns.iter().any(|c| &c.name == name);
+        self.columns.iter().all(exists)
+            && self.sort_by.iter().all(exists)
+            && self.group_by.iter().all(exists)
+            && self.column_search_text.keys().all(exists)
+            && self.column_category_filter.keys().all(exists)
+            && self.column_widths.keys().all(exists)
+            && self.pinned_columns.keys().all(exists)
+    }
+
+    /// Forget the columns that no longer exist, along with their filters, widths and pins
+    pub fn retain_columns(&mut self, columns: &[Column]) {
+        let exists = |name: &String| columns.iter().any(|c| &c.name == name);
+        self.columns.retain(exists);
+        self.sort_by = self.sort_by.take().filter(exists);
+        if self.group_by.as_ref().is_some_and(|name| !exists(name)) {
+            self.set_group_by(None);
+        }
+        self.column_search_text.retain(|name, _| exists(name));
+        self.column_category_filter.retain(|name, _| exists(name));
+        self.column_widths.retain(|name, _| exists(name));
+        self.pinned_columns.retain(|name, _| exists(name));
+    }
+
+    /// Snapshot of this state as a view with the given name
+    pub fn to_view(&self, name: String) -> View {
+        View {
+            name,
+            columns: self.columns.clone(),
+            sort_by: self.sort_by.clone().unwrap_or_default(),
+            sort_ascending: self.sort_ascending,
+            search_text: self.search_text.clone(),
+            fuzzy_search: self.fuzzy_search,
+            column_search_text: self.column_search_text.clone(),
+            column_category_filter: self.column_category_filter.clone(),
+            column_widths: self.column_widths.clone(),
+            pinned_columns: self.pinned_columns.clone(),
+            group_by: self.group_by.clone(),
+        }
+    }
+
+    /// Whether a row passes the search text and all column filters. A column filter matches cells
+    /// containing its text or with a value equal to one of its selected categories.
+    pub fn matches(&self, columns: &[Column], row: &[String]) -> bool {
+        let search_text = self.search_text.to_lowercase();
+        let search_matches = if self.fuzzy_search {
+            fuzzy::score_row(&search_text, row).is_some()
+        } else {
+            row.iter()
+                .any(|cell| cell.to_lowercase().contains(&search_text))
+        };
+        search_matches
+            && columns.iter().zip(row).all(|(column, cell)| {
+                let filter_text = self
+                    .column_search_text
+                    .get(&column.name)
+                    .map(|text| text.to_lowercase())
+                    .unwrap_or_default();
+                let category_filter = self.column_category_filter.get(&column.name);
+                (filter_text.is_empty() && category_filter.is_none())
+                    || (!filter_text.is_empty() && cell.to_lowercase().contains(&filter_text))
+                    || category_filter
+                        .is_some_and(|set| column.values(cell).iter().any(|v| set.contains(*v)))
+            })
+    }
+
+    /// Whether any rows are filtered out by the search text or a column filter
+    pub fn is_filtering(&self) -> bool {
+        !self.search_text.trim().is_empty()
+            || !self.column_search_text.is_empty()
+            || !self.column_category_filter.is_empty()
+    }
+
+    /// Whether rows are filtered by the given column
+    pub fn is_filtered(&self, column: &str) -> bool {
+        self.column_search_text.contains_key(column)
+            || self.column_category_filter.contains_key(column)
+    }
+
+    pub fn set_column_search_text(&mut self, column: &str, text: String) {
+        if text.is_empty() {
+            self.column_search_text.remove(column);
+        } else {
+            self.column_search_text.insert(column.to_string(), text);
+        }
+    }
+
+    /// Select or deselect a category in the filter of a column
+    pub fn toggle_category(&mut self, column: &str, value: &str) {
+        let set = self
+            .column_category_filter
+            .entry(column.to_string())
+            .or_default();
+        if !set.remove(value) {
+            set.insert(value.to_string());
+        }
+        if set.is_empty() {
+            self.column_category_filter.remove(column);
+        }
+    }
+
+    pub fn reset_filter(&mut self, column: &str) {
+        self.column_search_text.remove(column);
+        self.column_category_filter.remove(column);
+    }
+
+    /// Positions of the characters of a cell matched by the search text
+    pub fn highlights(&self, cell: &str) -> Vec<usize> {
+        fuzzy::highlights(&self.search_text, cell, self.fuzzy_search)
+    }
+
+    /// Whether rows are ordered by how well they match a fuzzy search instead of by a column
+    pub fn ranked(&self) -> bool {
+        self.fuzzy_search && self.sort_by.is_none() && !self.search_text.trim().is_empty()
+    }
+
+    /// The column rows are sorted by: the explicitly chosen one, else the first column unless rows
+    /// are ranked by search
+    pub fn sort_column<'a>(&'a self, columns: &'a [Column]) -> Option<&'a str> {
+        match &self.sort_by {
+            Some(column) => Some(column),
+            None if self.ranked() => None,
+            None => columns.first().map(|c| c.name.as_str()),
+        }
+    }
+
+    /// Sort by the given column, or reverse the order if already sorted by it
+    pub fn toggle_sort(&mut self, columns: &[Column], column: &str) {
+        if self.sort_column(columns) == Some(column) {
+            self.sort_ascending = !self.sort_ascending;
+        } else {
+            self.sort_ascending = true;
+        }
+        self.sort_by = Some(column.to_string());
+    }
+
+    /// Sort by the default sort column again, so rows are ranked while searching fuzzily
+    pub fn clear_sort(&mut self) {
+        self.sort_by = None;
+        self.sort_ascending = true;
+    }
+
+    /// Sort rows by the sort column, or by match quality if ranked, keeping the order of equal rows
+    pub fn sort<R: AsRef<[String]>>(&self, columns: &[Column], rows: &mut [(usize, R)]) {
+        let Some(name) = self.sort_column(columns) else {
+            // Best matches first
+            rows.sort_by_cached_key(|(_, row)| {
+                std::cmp::Reverse(fuzzy::score_row(&self.search_text, row.as_ref()))
+            });
+            return;
+        };
+        let Some((idx, column)) = columns.iter().find_position(|c| c.name == name) else {
+            return;
+        };
+        let key = |(_, row): &(usize, R)| {
+            column.sort_key(row.as_ref().get(idx).map_or("", String::as_str))
+        };
+        // Comparing in reverse instead of reversing the sorted rows keeps ties in order
+        if self.sort_ascending {
+            rows.sort_by_cached_key(key);
+        } else {
+            rows.sort_by_cached_key(|row| std::cmp::Reverse(key(row)));
+        }
+    }
+
+    /// The cells of a row in the visible columns, in display order
+    pub fn project(&self, columns: &[Column], row: &[String]) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter_map(|name| {
+                columns
+                    .iter()
+                    .position(|c| &c.name == name)
+                    .and_then(|idx| row.get(idx).cloned())
+            })
+            .collect()
+    }
+
+    /// Filter, sort and project the given rows, keyed by their index in the data
+    pub fn rows<'a>(
+        &self,
+        columns: &[Column],
+        data: impl IntoIterator<Item = Row<'a>>,
+    ) -> Vec<(usize, Vec<String>)> {
+        let mut rows = data
+            .into_iter()
+            .filter(|(_, row)| self.matches(columns, row))
+            .collect::<Vec<_>>();
+        self.sort(columns, &mut rows);
+        rows.into_iter()
+            .map(|(id, row)| (id, self.project(columns, &row)))
+            .collect()
+    }
+
+    /// Filter, sort and project rows arranged in a tree, in depth-first order. Rows are shown if
+    /// they match or one of their descendants matches, and children follow their parent if it is
+    /// expanded. While filtering, parents of matching rows are expanded. Siblings are sorted among
+    /// themselves.
+    pub fn tree_rows<'a>(
+        &self,
+        columns: &[Column],
+        data: impl IntoIterator<Item = Row<'a>>,
+        tree: &[TreeNode],
+        expanded: &HashSet<usize>,
+    ) -> Vec<(usize, Vec<String>)> {
+        let parent = |id: usize| tree.get(id).and_then(|node| node.parent);
+        let data = data.into_iter().collect::<Vec<_>>();
+        // Matching rows and their ancestors
+        let mut shown = HashSet::new();
+        let mut has_match_below = HashSet::new();
+        for (id, row) in &data {
+            if self.matches(columns, row) {
+                shown.insert(*id);
+                let mut ancestor = parent(*id);
+                while let Some(id) = ancestor {
+                    if !has_match_below.insert(id) {
+                        // The ancestors of this one were already added
+                        break;
+                    }
+                    shown.insert(id);
+                    ancestor = parent(id);
+                }
+            }
+        }
+        let mut children = HashMap::<Option<usize>, Vec<_>>::new();
+        for (id, row) in data.into_iter().filter(|(id, _)| shown.contains(id)) {
+            children.entry(parent(id)).or_default().push((id, row));
+        }
+        let filtering = self.is_filtering();
+        let is_open =
+            |id: usize| expanded.contains(&id) || (filtering && has_match_below.contains(&id));
+        let mut rows = Vec::new();
+        self.walk_tree(columns, None, &mut children, &is_open, &mut rows);
+        rows
+    }
+
+    fn walk_tree(
+        &self,
+        columns: &[Column],
+        parent: Option<usize>,
+        children: &mut HashMap<Option<usize>, Vec<Row<'_>>>,
+        is_open: &dyn Fn(usize) -> bool,
+        rows: &mut Vec<(usize, Vec<String>)>,
+    ) {
+        // Each list of children is taken out once, so cycles in the tree end here
+        let Some(mut siblings) = children.remove(&parent) else {
+            return;
+        };
+        self.sort(columns, &mut siblings);
+        for (id, row) in siblings {
+            rows.push((id, self.project(columns, &row)));
+            if is_open(id) {
+                self.walk_tree(columns, Some(id), children, is_open, rows);
+            }
+        }
+    }
+
+    /// Group rows by the group-by column, or a single unnamed group if not grouped. Groups are
+    /// sorted by key and keep the order of their rows. Rows are grouped by their cells in the data
+    /// with edits applied, as the group-by column needn't be visible.
+    pub fn groups<'a>(
+        &self,
+        columns: &[Column],
+        data: impl IntoIterator<Item = Row<'a>>,
+        rows: Vec<(usize, Vec<String>)>,
+    ) -> Vec<Group> {
+        let Some(idx) = self
+            .group_by
+            .as_ref()
+            .and_then(|name| columns.iter().position(|c| &c.name == name))
+        else {
+            return vec![Group::ungrouped(rows)];
+        };
+        let data = data.into_iter().collect::<HashMap<_, _>>();
+        let column = &columns[idx];
+        let kind = column.kind;
+        rows.into_iter()
+            .into_group_map_by(|(id, _)| {
+                let cell = data.get(id).and_then(|row| row.get(idx));
+                kind.group_key(cell.map_or("", String::as_str))
+            })
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| match kind {
+                // Date groups are keyed by day, which sorts lexicographically
+                ColumnKind::Date => a.cmp(b),
+                _ => column.compare(a, b),
+            })
+            .map(|(key, rows)| Group {
+                label: match kind {
+                    ColumnKind::Bytes => kind.display(&key),
+                    _ => key.clone(),
+                },
+                aggregates: aggregates(
+                    columns,
+                    &rows
+                        .iter()
+                        .filter_map(|(id, _)| data.get(id).map(AsRef::as_ref))
+                        .collect::<Vec<_>>(),
+                ),
+                collapsed: self.collapsed_groups.contains(&key),
+                key,
+                rows,
+            })
+            .collect()
+    }
+
+    pub fn set_group_by(&mut self, column: Option<String>) {
+        self.group_by = column;
+        self.collapsed_groups.clear();
+    }
+
+    pub fn toggle_group(&mut self, key: &str) {
+        if !self.collapsed_groups.remove(key) {
+            self.collapsed_groups.insert(key.to_string());
+        }
+    }
+
+    /// Move the visible column at `from` to position `to`. Pinned columns stay at the edges.
+    pub fn move_column(&mut self, from: usize, to: usize) {
+        if from < self.columns.len() {
+            let column = self.columns.remove(from);
+            self.columns.insert(to.min(self.columns.len()), column);
+            order_pinned(&mut self.columns, &self.pinned_columns);
+        }
+    }
+
+    /// Show or hide a column. Shown columns are inserted before the first visible column that
+    /// comes after them in the column definitions.
+    pub fn toggle_column(&mut self, columns: &[Column], name: &str) {
+        if self.columns.iter().any(|c| c == name) {
+            self.columns.retain(|c| c != name);
+            return;
+        }
+        let position = |name: &str| columns.iter().position(|c| c.name == name);
+        let Some(pos) = position(name) else {
+            return;
+        };
+        let insert_at = self
+            .columns
+            .iter()
+            .position(|c| position(c).is_some_and(|col_pos| col_pos > pos))
+            .unwrap_or(self.columns.len());
+        self.columns.insert(insert_at, name.to_string());
+        order_pinned(&mut self.columns, &self.pinned_columns);
+    }
+
+    /// Pin a column to the given side, or unpin it if already pinned there
+    pub fn toggle_pin(&mut self, column: &str, pin: Pin) {
+        if self.pinned_columns.get(column) == Some(&pin) {
+            self.pinned_columns.remove(column);
+        } else {
+            self.pinned_columns.insert(column.to_string(), pin);
+        }
+        order_pinned(&mut self.columns, &self.pinned_columns);
+    }
+
+    /// Restore the default columns, widths and pins
+    pub fn reset_columns(&mut self, columns: &[Column]) {
+        self.columns = default_columns(columns);
+        self.column_widths.clear();
+        self.pinned_columns.clear();
+    }
+}
+
+/// The headers and rows of the data as a table shows them in a view, as exported from it
+#[cfg(any(feature = "server", test))]
+pub fn view_rows(
+    columns: &[Column],
+    data: &[Vec<String>],
+    view: &View,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut state = TableState::new(columns);
+    state.apply_view(columns, view);
+    let rows = state.rows(columns, apply_edits(data, &HashMap::new()));
+    (
+        state.columns,
+        rows.into_iter().map(|(_, row)| row).collect(),
+    )
+}
+
+fn default_columns(columns: &[Column]) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|c| !c.hidden)
+        .map(|c| c.name.clone())
+        .collect()
+}
+
+/// Move pinned columns to the edges, keeping the order within each group
+pub fn order_pinned(columns: &mut [String], pins: &BTreeMap<String, Pin>) {
+    columns.sort_by_key(|c| match pins.get(c) {
+        Some(Pin::Left) => 0,
+        None => 1,
+        Some(Pin::Right) => 2,
+    });
+}
+
+/// Summarize the given rows: the total of each byte column and the newest value of each date column
+fn aggregates(columns: &[Column], rows: &[&[String]]) -> Vec<String> {
+    columns
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, column)| {
+            let cells = rows.iter().filter_map(|row| row.get(idx));
+            match column.kind {
+                ColumnKind::Text => None,
+                ColumnKind::Number => {
+                    let total = cells.filter_map(|c| parse_number(c)).sum::<f64>();
+                    Some(format!("{}: {total} total", column.name))
+                }
+                ColumnKind::Bytes => {
+                    let total = cells.filter_map(|c| c.parse::<u64>().ok()).sum();
+                    Some(format!(
+                        "{}: {} total",
+                        column.name,
+                        crate::format_bytes(total)
+                    ))
+                }
+                ColumnKind::Date => cells
+                    .filter(|c| parse_date(c).is_some())
+                    .max_by_key(|c| parse_date(c))
+                    .map(|newest| {
+                        format!("{}: newest {}", column.name, column.kind.display(newest))
+                    }),
+            }
+        })
+        .collect()
+}
+
+/// Footer aggregates of a column as label and value: the count of non-empty cells, sum and average
+/// for numeric and byte columns and the earliest and latest value for date columns
+pub fn footer_aggregates<'a>(
+    kind: ColumnKind,
+    cells: impl Iterator<Item = &'a str>,
+) -> Vec<(&'static str, String)> {
+    let cells = cells.filter(|c| !c.is_empty()).collect::<Vec<_>>();
+    let mut result = vec![("Count", cells.len().to_string())];
+    match kind {
+        ColumnKind::Text => {}
+        ColumnKind::Number | ColumnKind::Bytes => {
+            let values = cells
+                .iter()
+                .filter_map(|c| parse_number(c))
+                .collect::<Vec<_>>();
+            let sum = values.iter().sum::<f64>();
+            let format = |value: f64| match kind {
+                ColumnKind::Bytes => crate::format_bytes(value.round() as u64),
+                _ => format!("{:.2}", value)
+                    .trim_end_matches('0')
+                    .trim_end_matches('.')
+                    .to_string(),
+            };
+            result.push(("Sum", format(sum)));
+            if !values.is_empty() {
+                result.push(("Average", format(sum / values.len() as f64)));
+            }
+        }
+        ColumnKind::Date => {
+            let dates = cells
+                .iter()
+                .filter(|c| parse_date(c).is_some())
+                .collect::<Vec<_>>();
+            let min = dates.iter().min_by_key(|c| parse_date(c));
+            let max = dates.iter().max_by_key(|c| parse_date(c));
+            if let (Some(min), Some(max)) = (min, max) {
+                result.push(("Earliest", kind.display(min)));
+                result.push(("Latest", kind.display(max)));
+            }
+        }
+    }
+    result
+}
+
+/// Number of distinct values and the most frequent values with their counts
+pub fn value_counts<'a>(
+    cells: impl Iterator<Item = &'a str>,
+    top: usize,
+) -> (usize, Vec<(String, usize)>) {
+    let counts = cells.counts();
+    let distinct = counts.len();
+    let top_values = counts
+        .into_iter()
+        .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)))
+        .take(top)
+        .map(|(value, count)| (value.to_string(), count))
+        .collect();
+    (distinct, top_values)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("Name"),
+            Column::new("Count").numeric(),
+            Column::new("Tags").categorical().list(),
+            Column::new("Notes").hidden(),
+        ]
+    }
+
+    fn names() -> Vec<String> {
+        columns().into_iter().map(|c| c.name).collect()
+    }
+
+    fn row() -> impl Strategy<Value = Vec<String>> {
+        // Counts with leading zeros compare equal to those without, so sorting sees ties
+        (
+            "[a-c]{0,2}",
+            "0?[0-9]",
+            prop::sample::subsequence(vec!["x", "y", "z"], 0..=3).prop_map(|tags| tags.join(", ")),
+            "[a-c ]{0,3}",
+        )
+            .prop_map(|(name, count, tags, notes)| vec![name, count, tags, notes])
+    }
+
+    fn data() -> impl Strategy<Value = Vec<Vec<String>>> {
+        prop::collection::vec(row(), 0..20)
+    }
+
+    fn pins() -> impl Strategy<Value = BTreeMap<String, Pin>> {
+        prop::collection::btree_map(
+            prop::sample::select(names()),
+            prop_oneof![Just(Pin::Left), Just(Pin::Right)],
+            0..3,
+        )
+    }
+
+    /// A state as the table produces it: existing columns without duplicates, pinned columns at
+    /// the edges and no empty filters
+    fn state() -> impl Strategy<Value = TableState> {
+        (
+            Just(names()).prop_shuffle(),
+            prop::collection::vec(any::<bool>(), 4),
+            prop::option::of(prop::sample::select(names())),
+            any::<bool>(),
+            "[a-c]{0,1}",
+            any::<bool>(),
+            prop::collection::btree_map(prop::sample::select(names()), "[a-c0-9]{1,2}", 0..2),
+            prop::collection::btree_map(
+                Just("Tags".to_string()),
+                prop::collection::btree_set(prop::sample::select(vec!["x", "y", "z"]), 1..3),
+                0..=1,
+            ),
+            pins(),
+            prop::option::of(prop::sample::select(names())),
+        )
+            .prop_map(
+                |(
+                    order,
+                    shown,
+                    sort_by,
+                    sort_ascending,
+                    search_text,
+                    fuzzy_search,
+                    column_search_text,
+                    column_category_filter,
+                    pinned_columns,
+                    group_by,
+                )| {
+                    let mut columns = order
+                        .into_iter()
+                        .zip(shown)
+                        .filter_map(|(name, shown)| shown.then_some(name))
+                        .collect::<Vec<_>>();
+                    order_pinned(&mut columns, &pinned_columns);
+                    TableState {
+                        columns,
+                        sort_by,
+                        sort_ascending,
+                        search_text,
+                        fuzzy_search,
+                        column_search_text,
+                        column_category_filter: column_category_filter
+                            .into_iter()
+                            .map(|(name, set)| (name, set.into_iter().map(String::from).collect()))
+                            .collect(),
+                        column_widths: BTreeMap::new(),
+                        pinned_columns,
+                        group_by,
+                        collapsed_groups: HashSet::new(),
+                    }
+                },
+            )
+    }
+
+    fn rows(state: &TableState, data: &[Vec<String>]) -> Vec<(usize, Vec<String>)> {
+        state.rows(
+            &columns(),
+            data.iter()
+                .enumerate()
+                .map(|(id, row)| (id, Cow::Borrowed(row.as_slice()))),
+        )
+    }
+
+    /// Whether pinned columns come first if left and last if right
+    fn pinned_at_edges(state: &TableState) -> bool {
+        let rank = |c: &String| match state.pinned_columns.get(c) {
+            Some(Pin::Left) => 0,
+            None => 1,
+            Some(Pin::Right) => 2,
+        };
+        state.columns.iter().map(rank).is_sorted()
+    }
+
+    #[test]
+    fn aggregates_skip_non_finite_numbers() {
+        let cells = ["2", "NaN", "inf", "-infinity", "1.5", "x"];
+        assert_eq!(
+            footer_aggregates(ColumnKind::Number, cells.into_iter()),
+            vec![
+                ("Count", "6".to_string()),
+                ("Sum", "3.5".to_string()),
+                ("Average", "1.75".to_string()),
+            ]
+        );
+        let rows = [
+            vec![String::new(), "NaN".to_string()],
+            vec![String::new(), "4".to_string()],
+        ];
+        assert_eq!(
+            aggregates(
+                &columns(),
+                &rows.iter().map(Vec::as_slice).collect::<Vec<_>>()
+            ),
+            vec!["Count: 4 total"]
+        );
+    }
+
+    #[test]
+    fn edited_rows_move_to_their_new_group() {
+        let columns = columns();
+        let mut state = TableState::new(&columns);
+        state.set_group_by(Some("Name".to_string()));
+        let data = vec![
+            vec!["a".to_string(), "1".to_string()],
+            vec!["a".to_string(), "2".to_string()],
+        ];
+        let edits = HashMap::from([(1, HashMap::from([(0, "b".to_string())]))]);
+        let rows = state.rows(&columns, apply_edits(&data, &edits));
+        let groups = state.groups(&columns, apply_edits(&data, &edits), rows);
+        let keys = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.key.as_str(),
+                    g.rows.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [("a", vec![0]), ("b", vec![1])]);
+        assert_eq!(groups[1].aggregates, ["Count: 2 total"]);
+    }
+
+    proptest! {
+        #[test]
+        fn filtering_never_adds_rows(state in state(), data in data()) {
+            let rows = rows(&state, &data);
+            prop_assert!(rows.len() <= data.len());
+            prop_assert!(rows.iter().map(|(id, _)| id).all_unique());
+        }
+
+        #[test]
+        fn rows_are_the_projected_matches(state in state(), data in data()) {
+            let columns = columns();
+            let rows = rows(&state, &data);
+            let ids = rows.iter().map(|(id, _)| *id).collect::<BTreeSet<_>>();
+            for (id, row) in data.iter().enumerate() {
+                prop_assert_eq!(ids.contains(&id), state.matches(&columns, row));
+            }
+            for (id, projected) in rows {
+                prop_assert_eq!(projected, state.project(&columns, &data[id]));
+            }
+        }
+
+        #[test]
+        fn projection_follows_visible_columns(state in state(), row in row()) {
+            let columns = columns();
+            let projected = state.project(&columns, &row);
+            let expected = state
+                .columns
+                .iter()
+                .map(|name| row[columns.iter().position(|c| &c.name == name).unwrap()].clone())
+                .collect::<Vec<_>>();
+            prop_assert_eq!(projected, expected);
+        }
+
+        #[test]
+        fn sort_is_ordered_and_stable(
+            mut state in state(),
+            data in data(),
+            column in prop::sample::select(names()),
+        ) {
+            let columns = columns();
+            state.sort_by = Some(column.clone());
+            let idx = columns.iter().position(|c| c.name == column).unwrap();
+            let mut rows = data.iter().cloned().enumerate().collect::<Vec<_>>();
+            state.sort(&columns, &mut rows);
+            for pair in rows.windows(2) {
+                let (a, b) = (&pair[0], &pair[1]);
+                let ordering = columns[idx].compare(&a.1[idx], &b.1[idx]);
+                if state.sort_ascending {
+                    prop_assert_ne!(ordering, std::cmp::Ordering::Greater);
+                } else {
+                    prop_assert_ne!(ordering, std::cmp::Ordering::Less);
+                }
+                if ordering == std::cmp::Ordering::Equal {
+                    prop_assert!(a.0 < b.0, "equal rows {} and {} swapped", a.0, b.0);
+                }
+            }
+        }
+
+        #[test]
+        fn number_sort_is_a_total_order(
+            cells in prop::collection::vec(
+                prop_oneof![
+                    // Not finite, overflowing, zeros of either sign or not numbers
+                    prop::sample::select(vec!["NaN", "-NaN", "inf", "-infinity", "1e400", "-0", "0", "", "x"])
+                        .prop_map(String::from),
+                    "-?[0-9]{1,2}(\\.[0-9])?",
+                ],
+                0..50,
+            ),
+            sort_ascending in any::<bool>(),
+        ) {
+            let columns = columns();
+            let state = TableState {
+                sort_by: Some("Count".to_string()),
+                sort_ascending,
+                ..TableState::new(&columns)
+            };
+            let mut rows = cells
+                .iter()
+                .map(|cell| vec![String::new(), cell.clone(), String::new(), String::new()])
+                .enumerate()
+                .collect::<Vec<_>>();
+            state.sort(&columns, &mut rows);
+            let count = &columns[1];
+            for (i, a) in rows.iter().enumerate() {
+                for b in &rows[i + 1..] {
+                    let ordering = count.compare(&a.1[1], &b.1[1]);
+                    prop_assert_eq!(ordering, count.compare(&b.1[1], &a.1[1]).reverse());
+                    if sort_ascending {
+                        prop_assert_ne!(ordering, std::cmp::Ordering::Greater);
+                    } else {
+                        prop_assert_ne!(ordering, std::cmp::Ordering::Less);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn groups_partition_ragged_rows(
+            mut state in state(),
+            data in prop::collection::vec(
+                row().prop_flat_map(|row| {
+                    let len = row.len();
+                    (Just(row), 0..=len).prop_map(|(mut row, len)| {
+                        row.truncate(len);
+                        row
+                    })
+                }),
+                0..20,
+            ),
+            group_by in prop::sample::select(names()),
+        ) {
+            let columns = columns();
+            state.set_group_by(Some(group_by.clone()));
+            let idx = columns.iter().position(|c| c.name == group_by).unwrap();
+            let data_rows = || data.iter().enumerate().map(|(id, row)| (id, Cow::Borrowed(row.as_slice())));
+            let rows = state.rows(&columns, data_rows());
+            let groups = state.groups(&columns, data_rows(), rows.clone());
+            prop_assert!(groups.iter().map(|g| &g.key).all_unique());
+            for group in &groups {
+                for (id, _) in &group.rows {
+                    let cell = data[*id].get(idx).map_or("", String::as_str);
+                    prop_assert_eq!(&group.key, &columns[idx].kind.group_key(cell));
+                }
+            }
+            let grouped = groups.into_iter().flat_map(|g| g.rows).map(|(id, _)| id).sorted().collect::<Vec<_>>();
+            prop_assert_eq!(grouped, rows.into_iter().map(|(id, _)| id).sorted().collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn pinned_columns_stay_at_edges_after_move(
+            mut state in state(),
+            moves in prop::collection::vec((0..5usize, 0..5usize), 1..5),
+        ) {
+            for (from, to) in moves {
+                state.move_column(from, to);
+                prop_assert!(pinned_at_edges(&state), "{:?}", state.columns);
+            }
+        }
+
+        #[test]
+        fn toggling_a_column_twice_keeps_the_others(
+            mut state in state(),
+            column in prop::sample::select(names()),
+        ) {
+            let columns = columns();
+            let before = state.columns.clone();
+            state.toggle_column(&columns, &column);
+            prop_assert!(pinned_at_edges(&state));
+            prop_assert_ne!(state.columns.contains(&column), before.contains(&column));
+            prop_assert!(state.columns.iter().all_unique());
+            state.toggle_column(&columns, &column);
+            prop_assert!(pinned_at_edges(&state));
+            let others = |c: &Vec<String>| c.iter().filter(|c| **c != column).cloned().collect::<Vec<_>>();
+            prop_assert_eq!(others(&state.columns), others(&before));
+        }
+
+        #[test]
+        fn order_pinned_keeps_order_within_sides(mut order in Just(names()).prop_shuffle(), pins in pins()) {
+            let before = order.clone();
+            order_pinned(&mut order, &pins);
+            let side = |c: &&String| pins.get(*c).copied();
+            for pin in [Some(Pin::Left), None, Some(Pin::Right)] {
+                let kept = |c: &Vec<String>| c.iter().filter(|c| side(c) == pin).cloned().collect::<Vec<_>>();
+                prop_assert_eq!(kept(&order), kept(&before));
+            }
+        }
+
+        #[test]
+        fn retained_columns_exist(
+            mut state in state(),
+            data in data(),
+            removed in prop::sample::subsequence(names(), 0..=4),
+        ) {
+            let columns = columns()
+                .into_iter()
+                .filter(|c| !removed.contains(&c.name))
+                .collect::<Vec<_>>();
+            let data = data
+                .into_iter()
+                .map(|row| {
+                    names()
+                        .iter()
+                        .zip(row)
+                        .filter(|(name, _)| !removed.contains(name))
+                        .map(|(_, cell)| cell)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            state.retain_columns(&columns);
+            prop_assert!(state.has_only(&columns));
+            prop_assert!(pinned_at_edges(&state));
+            let rows = state.rows(
+                &columns,
+                data.iter().enumerate().map(|(id, row)| (id, Cow::Borrowed(row.as_slice()))),
+            );
+            for (_, row) in rows {
+                prop_assert_eq!(row.len(), state.columns.len());
+            }
+        }
+
+        #[test]
+        fn views_export_the_shown_rows(state in state(), data in data()) {
+            let view = state.to_view("View".to_string());
+            let shown = rows(&state, &data).into_iter().map(|(_, row)| row).collect::<Vec<_>>();
+            prop_assert_eq!(view_rows(&columns(), &data, &view), (state.columns, shown));
+        }
+
+        #[test]
+        fn view_round_trips(state in state()) {
+            let columns = columns();
+            let view = state.to_view("View".to_string());
+            let mut restored = TableState::new(&columns);
+            restored.apply_view(&columns, &view);
+            prop_assert_eq!(&restored, &state);
+            prop_assert_eq!(restored.to_view("View".to_string()), view);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Approximate matching of search queries against rows
+
+/// Score of the best match of a query in a row, or `None` if a token of the query doesn't match
+/// any cell. Tokens are matched independently, so their order doesn't matter. Higher is better.
+pub fn score_row(query: &str, row: &[String]) -> Option<u32> {
+    let cells = row.iter().map(|cell| chars(cell)).collect::<Vec<_>>();
+    tokens(query)
+        .map(|token| {
+            cells
+                .iter()
+                .filter_map(|cell| match_token(&token, cell).map(|m| m.score))
+                .max()
+        })
+        .sum()
+}
+
+/// Positions of the characters of a cell matched by the query: by any of its tokens if fuzzy, else
+/// by the whole query as a substring
+pub fn highlights(query: &str, cell: &str, fuzzy: bool) -> Vec<usize> {
+    let cell = chars(cell);
+    let mut positions = if fuzzy {
+        tokens(query)
+            .filter_map(|token| match_token(&token, &cell))
+            .flat_map(|m| m.positions)
+            .collect::<Vec<_>>()
+    } else {
+        substring(&chars(query), &cell)
+            .map(|m| m.positions)
+            .unwrap_or_default()
+    };
+    positions.sort_unstable();
+    positions.dedup();
+    positions
+}
+
+struct Match {
+    score: u32,
+    /// Character positions of the matched characters in the cell
+    positions: Vec<usize>,
+}
+
+/// Lowercased characters, one per character of the original so positions stay valid
+fn chars(s: &str) -> Vec<char> {
+    s.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+fn tokens(query: &str) -> impl Iterator<Item = Vec<char>> + '_ {
+    query.split_whitespace().map(chars)
+}
+
+/// Match a token as a substring, else as a subsequence with gaps, else with a few typos
+fn match_token(token: &[char], cell: &[char]) -> Option<Match> {
+    substring(token, cell)
+        .or_else(|| subsequence(token, cell))
+        .or_else(|| with_typos(token, cell))
+}
+
+fn substring(token: &[char], cell: &[char]) -> Option<Match> {
+    let start = (0..=cell.len().checked_sub(token.len())?)
+        .find(|&start| cell[start..].starts_with(token))?;
+    // Prefer matches at the start of a word
+    let word_start = start == 0 || !cell[start - 1].is_alphanumeric();
+    Some(Match {
+        score: 100 + if word_start { 20 } else { 0 },
+        positions: (start..start + token.len()).collect(),
+    })
+}
+
+/// All characters of the token in order, with other characters in between
+fn subsequence(token: &[char], cell: &[char]) -> Option<Match> {
+    let mut positions = Vec::with_capacity(token.len());
+    let mut rest = 0;
+    for c in token {
+        let pos = rest + cell[rest..].iter().position(|x| x == c)?;
+        positions.push(pos);
+        rest = pos + 1;
+    }
+    let gaps = positions.windows(2).filter(|w| w[1] != w[0] + 1).count() as u32;
+    Some(Match {
+        score: 80u32.saturating_sub(10 * gaps).max(20),
+        positions,
+    })
+}
+
+/// The substring of the cell with the smallest edit distance to the token, if the distance is
+/// small for the token's length. Swapping two adjacent characters counts as one edit.
+fn with_typos(token: &[char], cell: &[char]) -> Option<Match> {
+    let max_errors = match token.len() {
+        0..=3 => return None,
+        4..=6 => 1,
+        _ => 2,
+    };
+    // distances[i][j]: edit distance between the first i characters of the token and the best
+    // substring of the cell ending at j
+    let mut distances = vec![vec![0; cell.len() + 1]; token.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for i in 1..=token.len() {
+        for j in 1..=cell.len() {
+            let substitution = usize::from(token[i - 1] != cell[j - 1]);
+            distances[i][j] = (distances[i - 1][j - 1] + substitution)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if transposed(token, cell, i, j) {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    let last = &distances[token.len()];
+    let (mut end, &errors) = last.iter().enumerate().min_by_key(|(_, d)| **d)?;
+    if errors > max_errors {
+        return None;
+    }
+    // Extend the match over characters that don't cost more, so a transposition at the end is
+    // matched instead of dropping the token's last character
+    while last.get(end + 1) == Some(&errors) {
+        end += 1;
+    }
+    // Walk back to find which characters of the cell matched the token
+    let mut positions = Vec::new();
+    let (mut i, mut j) = (token.len(), end);
+    while i > 0 && j > 0 {
+        let substitution = usize::from(token[i - 1] != cell[j - 1]);
+        if transposed(token, cell, i, j) && distances[i][j] == distances[i - 2][j - 2] + 1 {
+            positions.extend([j - 1, j - 2]);
+            i -= 2;
+            j -= 2;
+        } else if distances[i][j] == distances[i - 1][j - 1] + substitution {
+            if substitution == 0 {
+                positions.push(j - 1);
+            }
+            i -= 1;
+            j -= 1;
+        } else if distances[i][j] == distances[i - 1][j] + 1 {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+    positions.reverse();
+    Some(Match {
+        score: 40 - 15 * errors as u32,
+        positions,
+    })
+}
+
+/// Whether the characters before `i` in the token and before `j` in the cell are the same two
+/// characters in swapped order
+fn transposed(token: &[char], cell: &[char], i: usize, j: usize) -> bool {
+    i > 1
+        && j > 1
+        && token[i - 1] == cell[j - 2]
+        && token[i - 2] == cell[j - 1]
+        && token[i - 1] != token[i - 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, cell: &str) -> Option<u32> {
+        score_row(query, &[cell.to_string()])
+    }
+
+    #[test]
+    fn substrings_rank_above_subsequences_above_typos() {
+        let exact = score("file", "file.txt").unwrap();
+        let inside = score("file", "profile.txt").unwrap();
+        let subsequence = score("file", "f_i_le.txt").unwrap();
+        let typo = score("fiel", "file.txt").unwrap();
+        assert!(exact > inside, "{exact} {inside}");
+        assert!(inside > subsequence, "{inside} {subsequence}");
+        assert!(subsequence > typo, "{subsequence} {typo}");
+        assert_eq!(score("report", "notes.txt"), None);
+    }
+
+    #[test]
+    fn typos_depend_on_token_length() {
+        // A transposition is a single edit
+        assert!(score("fiel", "file").is_some());
+        assert_eq!(score("fle", "fl_e_"), Some(70));
+        // Too short for typos
+        assert_eq!(score("fxe", "file"), None);
+        assert!(score("raport", "report").is_some());
+        assert_eq!(score("rapurt", "report"), None);
+        assert!(score("rapurts", "reports").is_some());
+    }
+
+    #[test]
+    fn tokens_match_in_any_cell_and_order() {
+        let row = ["scan.dcm".to_string(), "Erika".to_string()];
+        assert_eq!(score_row("erika scan", &row), score_row("scan erika", &row));
+        assert_eq!(score_row("erika report", &row), None);
+    }
+
+    #[test]
+    fn highlights_skip_typos() {
+        // The transposed characters are both matched
+        assert_eq!(highlights("fiel", "my file.txt", true), [3, 4, 5, 6]);
+        // The substituted character isn't
+        assert_eq!(highlights("raport", "report.pdf", true), [0, 2, 3, 4, 5]);
+        assert_eq!(highlights("f le", "my file.txt", true), [3, 5, 6]);
+        assert_eq!(highlights("le", "my file.txt", false), [5, 6]);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(score("", "file"), Some(0));
+        assert_eq!(score("file", ""), None);
+        assert_eq!(score_row("file", &[]), None);
+        assert!(highlights("", "file", true).is_empty());
+        assert!(highlights("", "file", false).is_empty());
+        assert!(highlights("file", "", true).is_empty());
+    }
+
+    #[test]
+    fn positions_count_characters() {
+        assert_eq!(highlights("äpfel", "Ein ÄPFEL", false), [4, 5, 6, 7, 8]);
+        // ß is one character, matched approximately by s
+        assert_eq!(highlights("strase", "Straße 1", true), [0, 1, 2, 3, 5]);
+        assert!(score("ÄPFEL", "äpfel").is_some());
+    }
+}